@@ -24,6 +24,10 @@
  * based on pulseaudio source code, see https://gitlab.freedesktop.org/pulseaudio/pulseaudio/-/blob/master/src/pulse/volume.c#L336
 */
 #define VOLUME(N) (uint32_t)(((uint64_t)N * 100 + (uint64_t)PA_VOLUME_NORM / 2) / (uint64_t)PA_VOLUME_NORM)
+/*
+ * inverse of VOLUME, get a pa_volume_t from a humanized volume
+ */
+#define PA_VOLUME(N) (pa_volume_t)(((uint64_t)N * (uint64_t)PA_VOLUME_NORM + 50) / 100)
 
 typedef struct timespec
         t_timespec;
@@ -33,12 +37,41 @@ typedef struct volume {
     bool mute;
 } t_volume;
 
+typedef enum target {
+    SINK,
+    SOURCE
+} t_target;
+
+typedef enum command_kind {
+    VOLUME_UP,
+    VOLUME_DOWN,
+    SET_VOLUME,
+    TOGGLE_MUTE
+} t_command_kind;
+
+/*
+ * command sent from baru to the pulse loop
+ * value is a volume step or an absolute volume, in percent
+ */
+typedef struct command {
+    t_target target;
+    t_command_kind kind;
+    uint32_t value;
+} t_command;
+
 typedef void(*send_cb)(void *, uint32_t, bool);
+/*
+ * poll the next pending command
+ * returns false when there is no pending command
+ */
+typedef bool(*command_cb)(void *, t_command *);
 
 typedef struct data {
     const char *name;
     bool use_default;
     t_volume volume;
+    pa_cvolume cvolume;
+    bool has_cvolume;
     send_cb cb;
     pa_operation *op;
 } t_data;
@@ -50,6 +83,7 @@ typedef struct main {
     pa_mainloop *mainloop;
     pa_mainloop_api *api;
     void *cb_context;
+    command_cb cmd_cb;
     t_timespec start;
     pa_operation *server_op;
     t_data *sink;
@@ -62,6 +96,7 @@ void run(bool *running,
          const char *source_name,
          void *cb_context,
          send_cb,
-         send_cb);
+         send_cb,
+         command_cb);
 
 #endif //AUDIO_H
@@ -17,6 +17,7 @@ void init_data(t_data *data, const char *name, send_cb cb) {
     data->name = name;
     data->cb = cb;
     data->op = NULL;
+    data->has_cvolume = false;
     data->use_default = name == NULL ? true : false;
 }
 
@@ -46,6 +47,8 @@ void sink_info_cb(pa_context *context, const pa_sink_info *info, int eol, void *
     if (info != NULL && eol == 0) {
         m->sink->volume.mute = info->mute;
         m->sink->volume.volume = VOLUME(pa_cvolume_avg(&info->volume));
+        m->sink->cvolume = info->volume;
+        m->sink->has_cvolume = true;
         (*m->sink->cb)(m->cb_context, m->sink->volume.volume, m->sink->volume.mute);
     }
     if (eol != 0) {
@@ -62,6 +65,8 @@ void source_info_cb(pa_context *context, const pa_source_info *info, int eol,
     if (info != NULL && eol == 0) {
         m->source->volume.mute = info->mute;
         m->source->volume.volume = VOLUME(pa_cvolume_avg(&info->volume));
+        m->source->cvolume = info->volume;
+        m->source->has_cvolume = true;
         (*m->source->cb)(m->cb_context, m->source->volume.volume, m->source->volume.mute);
     }
     if (eol != 0) {
@@ -88,12 +93,15 @@ server_info_cb(pa_context *context, const pa_server_info *info, void *main) {
     if (info != NULL) {
         if (m->sink->use_default && (m->sink->name == NULL || strcmp(info->default_sink_name, m->sink->name) != 0)) {
             m->sink->name = name_switch(m->sink->name, info->default_sink_name);
+            // the volume of the previous sink must not be set on the new one
+            m->sink->has_cvolume = false;
             try_free_op(&m->sink->op);
             m->sink->op = pa_context_get_sink_info_by_name(m->context, m->sink->name, sink_info_cb, main);
         }
         if (m->source->use_default &&
             (m->source->name == NULL || strcmp(info->default_source_name, m->source->name) != 0)) {
             m->source->name = name_switch(m->source->name, info->default_source_name);
+            m->source->has_cvolume = false;
             try_free_op(&m->source->op);
             m->source->op = pa_context_get_source_info_by_name(m->context, m->source->name, source_info_cb, main);
         }
@@ -128,6 +136,58 @@ void subscription_cb(pa_context *context, pa_subscription_event_type_t t, uint32
     }
 }
 
+bool compute_volume(t_data *data, t_command *cmd, pa_cvolume *volume) {
+    if (!data->has_cvolume) {
+        return false;
+    }
+    *volume = data->cvolume;
+    switch (cmd->kind) {
+        case VOLUME_UP:
+            // a volume already raised past 100%, e.g. by pavucontrol, is not lowered
+            if (pa_cvolume_max(volume) > PA_VOLUME_NORM) {
+                return pa_cvolume_inc(volume, PA_VOLUME(cmd->value)) != NULL;
+            }
+            return pa_cvolume_inc_clamp(volume, PA_VOLUME(cmd->value), PA_VOLUME_NORM) != NULL;
+        case VOLUME_DOWN:
+            return pa_cvolume_dec(volume, PA_VOLUME(cmd->value)) != NULL;
+        case SET_VOLUME:
+            if (cmd->value > 100) {
+                cmd->value = 100;
+            }
+            return pa_cvolume_scale(volume, PA_VOLUME(cmd->value)) != NULL;
+        default:
+            return false;
+    }
+}
+
+void exec_command(t_main *main, t_command *cmd) {
+    t_data *data;
+    pa_cvolume volume;
+    pa_operation *op;
+
+    data = cmd->target == SINK ? main->sink : main->source;
+    // the tracked sink/source is not resolved yet
+    if (data->name == NULL) {
+        return;
+    }
+    op = NULL;
+    if (cmd->kind == TOGGLE_MUTE) {
+        if (cmd->target == SINK) {
+            op = pa_context_set_sink_mute_by_name(main->context, data->name, !data->volume.mute, NULL, NULL);
+        } else {
+            op = pa_context_set_source_mute_by_name(main->context, data->name, !data->volume.mute, NULL, NULL);
+        }
+    } else if (compute_volume(data, cmd, &volume)) {
+        if (cmd->target == SINK) {
+            op = pa_context_set_sink_volume_by_name(main->context, data->name, &volume, NULL, NULL);
+        } else {
+            op = pa_context_set_source_volume_by_name(main->context, data->name, &volume, NULL, NULL);
+        }
+    }
+    // the operation keeps running, the subscription reports the new volume
+    try_free_op(&op);
+}
+
 void abs_time_tick(t_timespec *start, t_timespec *end, uint32_t tick) {
     long int sec;
     long int nsec;
@@ -145,6 +205,7 @@ void abs_time_tick(t_timespec *start, t_timespec *end, uint32_t tick) {
 
 void iterate(t_main *main) {
     t_timespec tick;
+    t_command cmd;
     int res;
 
     // get the time at the start of an iteration
@@ -154,6 +215,11 @@ void iterate(t_main *main) {
     // get the absolute time of the next tick (start time + tick value)
     abs_time_tick(&main->start, &tick, main->tick);
 
+    // execute the pending commands
+    while ((*main->cmd_cb)(main->cb_context, &cmd)) {
+        exec_command(main, &cmd);
+    }
+
     // iterate the main loop
     while ((res = pa_mainloop_iterate(main->mainloop, 0, NULL)) > 0) {}
     if (res < 0) {
@@ -169,7 +235,7 @@ void iterate(t_main *main) {
 }
 
 void run(bool *running, uint32_t tick, const char *sink_name, const char *source_name, void *cb_context,
-        send_cb sink_cb, send_cb source_cb) {
+        send_cb sink_cb, send_cb source_cb, command_cb cmd_cb) {
     pa_proplist *proplist;
     t_main main;
     t_data sink;
@@ -182,6 +248,7 @@ void run(bool *running, uint32_t tick, const char *sink_name, const char *source
     main.tick = tick;
     main.connected = false;
     main.cb_context = cb_context;
+    main.cmd_cb = cmd_cb;
     main.mainloop = pa_mainloop_new();
     main.api = pa_mainloop_get_api(main.mainloop);
     main.server_op = NULL;
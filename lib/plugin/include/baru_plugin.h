@@ -0,0 +1,72 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+/*
+ * C ABI of the baru plugins.
+ *
+ * A plugin is a shared library, placed in `$XDG_CONFIG_HOME/baru/plugins/`,
+ * exporting the function `baru_plugin`. The plugin is a module referenced
+ * in the root format as `%{name}`, its config is the section `name` of the
+ * config file.
+ *
+ * Each instance of the module runs in its own thread: the functions of the
+ * plugin must be thread-safe and must not unwind (C++ exceptions, Rust
+ * panics). A crash of the plugin, e.g. a segfault, takes baru down.
+ */
+
+#ifndef BARU_PLUGIN_H
+#define BARU_PLUGIN_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* version of the ABI, incremented on each breaking change */
+#define BARU_PLUGIN_ABI 1
+
+/*
+ * Functions given by baru to a running instance, `ctx` being their first
+ * argument. They can be called from any thread, but only until `create`
+ * or `run` returns: `ctx` is no longer valid past it.
+ */
+typedef struct baru_host {
+    void *ctx;
+    /* false once the module must stop, `run` should then return */
+    bool (*running)(void *ctx);
+    /* sleep for `ms` milliseconds, until woken up or stopped */
+    void (*sleep)(void *ctx, uint64_t ms);
+    /*
+     * send a text value, `label` and `state` can be NULL.
+     * `state` is one of normal, high, low, charging, full, unknown, muted,
+     * disconnected, warning or critical.
+     * Returns false if the data has been rejected, e.g. invalid state
+     */
+    bool (*send)(void *ctx, const char *value, const char *label, const char *state);
+    /* send a number value with its unit, `max` is ignored if not positive */
+    bool (*send_number)(void *ctx, double value, const char *unit, double max,
+                        const char *label, const char *state);
+    /* set the error reported when `create` or `run` fails */
+    void (*error)(void *ctx, const char *message);
+} t_baru_host;
+
+typedef struct baru_plugin {
+    /* must be BARU_PLUGIN_ABI */
+    uint32_t abi;
+    /* module name, alphanumeric characters and underscores */
+    const char *name;
+    /*
+     * create an instance from its config, a YAML mapping of the options of
+     * the instance (empty if the module is not declared).
+     * Returns NULL on error
+     */
+    void *(*create)(const char *config, const t_baru_host *host);
+    /* run the instance until stopped, returns 0 on success */
+    int32_t (*run)(void *instance, const t_baru_host *host);
+    /* free the instance, once `run` has returned */
+    void (*destroy)(void *instance);
+} t_baru_plugin;
+
+/* exported by the plugin, the returned pointer must stay valid */
+const t_baru_plugin *baru_plugin(void);
+
+#endif /* BARU_PLUGIN_H */
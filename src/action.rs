@@ -0,0 +1,68 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::error::Error;
+use crate::pulse::{CommandKind, PulseCommand, Target};
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use std::thread;
+use tracing::{debug, error, instrument};
+
+/// Action triggered by an event on a module, e.g. a click
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Run a shell command
+    Command(String),
+    /// Run a built-in action
+    Builtin(Builtin),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Builtin {
+    /// Switch the module between its `format` and `alt_format`
+    ToggleFormat,
+    /// sound and mic modules, raise the volume by the given step in percent
+    VolumeUp(u32),
+    /// sound and mic modules, lower the volume by the given step in percent
+    VolumeDown(u32),
+    /// sound and mic modules, set the volume in percent
+    SetVolume(u32),
+    /// sound and mic modules, mute/unmute
+    ToggleMute,
+}
+
+impl Builtin {
+    /// Get the PulseAudio command of the action for the given sink/source
+    pub fn pulse_command(&self, target: Target) -> Option<PulseCommand> {
+        match self {
+            Builtin::VolumeUp(step) => Some(PulseCommand(target, CommandKind::VolumeUp, *step)),
+            Builtin::VolumeDown(step) => Some(PulseCommand(target, CommandKind::VolumeDown, *step)),
+            Builtin::SetVolume(v) => Some(PulseCommand(target, CommandKind::SetVolume, *v)),
+            Builtin::ToggleMute => Some(PulseCommand(target, CommandKind::ToggleMute, 0)),
+            Builtin::ToggleFormat => None,
+        }
+    }
+}
+
+/// Run a shell command in the background.
+/// Its output is discarded so it can't mess with the bar output.
+#[instrument]
+pub fn spawn_command(command: &str) -> Result<(), Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .inspect_err(|e| error!("failed to run command `{}`: {}", command, e))?;
+    debug!("command `{}` spawned", command);
+    // reap the child once it exits
+    thread::Builder::new()
+        .name("action".into())
+        .spawn(move || child.wait())?;
+    Ok(())
+}
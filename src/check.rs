@@ -0,0 +1,278 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::error::Error;
+use crate::module::{ModuleData, Registry};
+use crate::pulse;
+use crate::{Config, ROOT_OPTIONS, parse_format, parse_groups};
+use serde_yaml::Value;
+use std::fmt::{self, Display, Formatter};
+use tracing::instrument;
+
+/// Config problem and its line in the config file, if known
+#[derive(Debug)]
+pub struct Problem {
+    line: Option<usize>,
+    message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check the content of a config file: parse it, reject the unknown keys,
+/// check the root format and the config of the modules of the registry,
+/// as done when they start. Returns every problem found
+#[instrument(skip_all)]
+pub fn check(content: &str, registry: &Registry) -> Vec<Problem> {
+    let config: Config = match serde_yaml::from_str(content) {
+        Ok(c) => c,
+        Err(e) => {
+            return vec![Problem {
+                line: e.location().map(|l| l.line()),
+                message: e.to_string(),
+            }];
+        }
+    };
+    let mut problems = vec![];
+    let raw: Value = serde_yaml::from_str(content).unwrap_or_default();
+    match known_config(&config, registry) {
+        Ok(known) => {
+            let mut unknown = vec![];
+            unknown_keys(&raw, &known, "", &mut unknown);
+            for path in unknown {
+                problems.push(Problem {
+                    line: locate(content, &path),
+                    message: format!("unknown key \"{path}\""),
+                });
+            }
+        }
+        Err(e) => problems.push(Problem {
+            line: None,
+            message: e.to_string(),
+        }),
+    }
+    if let Err((name, e)) = pulse::check(&config) {
+        problems.push(Problem {
+            line: locate(content, name),
+            message: e.to_string(),
+        });
+    }
+    let format_line = locate(content, "format");
+    let format_problem = |e: Error| Problem {
+        line: format_line,
+        message: e.to_string(),
+    };
+    if let Err(e) = parse_groups(&config.format) {
+        problems.push(format_problem(e));
+    }
+    let markups = match parse_format(&config.format, registry) {
+        Ok(m) => m,
+        Err(e) => {
+            problems.push(format_problem(e));
+            vec![]
+        }
+    };
+    for markup in &markups {
+        let invalid = config
+            .section(&markup.0)
+            .and_then(|section| registry.known(&markup.0, section))
+            .is_some_and(|known| known.is_err());
+        if invalid {
+            // reported by the check of the module below
+            continue;
+        }
+        let instance = markup.2.as_deref();
+        if let Err(e) = ModuleData::new(&markup.0, instance, &config, registry) {
+            let path = instance_path(&config, &markup.0, instance);
+            problems.push(Problem {
+                line: locate(content, &path).or(format_line),
+                message: e.to_string(),
+            });
+        }
+    }
+    // run the check function of each module on each of its instances, or
+    // on the default config if the module is used without being declared
+    for name in registry.names() {
+        let used = markups.iter().any(|m| m.0 == name);
+        for (id, e) in registry.check(name, config.section(name), used) {
+            let message = match &id {
+                Some(id) => format!("{name}:{id}: {e}"),
+                None => format!("{name}: {e}"),
+            };
+            let path = instance_path(&config, name, id.as_deref());
+            problems.push(Problem {
+                line: locate(content, &path),
+                message,
+            });
+        }
+    }
+    problems
+}
+
+/// The config as understood by baru and its modules, without the unknown
+/// keys. The sections of the unknown modules are removed, the invalid
+/// sections are kept as is, their errors being reported by the modules check
+fn known_config(config: &Config, registry: &Registry) -> Result<Value, Error> {
+    let mut known = serde_yaml::to_value(config)?;
+    if let Value::Mapping(map) = &mut known {
+        map.retain(|key, section| {
+            let Some(name) = key.as_str() else {
+                return false;
+            };
+            if ROOT_OPTIONS.contains(&name) {
+                return true;
+            }
+            match registry.known(name, section) {
+                Some(Ok(k)) => {
+                    *section = k;
+                    true
+                }
+                Some(Err(_)) => true,
+                None => false,
+            }
+        });
+    }
+    Ok(known)
+}
+
+/// Collect the keys of `raw` which are missing from `known`,
+/// i.e. the keys ignored when deserializing the config
+fn unknown_keys(raw: &Value, known: &Value, path: &str, unknown: &mut Vec<String>) {
+    match (raw, known) {
+        (Value::Mapping(raw), Value::Mapping(known)) => {
+            for (key, value) in raw {
+                let name = match key {
+                    Value::String(s) => s.to_string(),
+                    k => serde_yaml::to_string(k)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                };
+                let child = match path {
+                    "" => name,
+                    p => format!("{p}.{name}"),
+                };
+                match known.get(key) {
+                    Some(k) => unknown_keys(value, k, &child, unknown),
+                    None => unknown.push(child),
+                }
+            }
+        }
+        (Value::Sequence(raw), Value::Sequence(known)) => {
+            for (i, (r, k)) in raw.iter().zip(known).enumerate() {
+                unknown_keys(r, k, &format!("{path}[{i}]"), unknown);
+            }
+        }
+        // a list with a single module instance is serialized as a map
+        (Value::Sequence(raw), Value::Mapping(_)) if raw.len() == 1 => {
+            unknown_keys(&raw[0], known, &format!("{path}[0]"), unknown);
+        }
+        _ => {}
+    }
+}
+
+/// The key path of a module instance, e.g. `battery[1]` for the instance
+/// `bat1` declared second, or the module name for its single instance
+fn instance_path(config: &Config, name: &str, id: Option<&str>) -> String {
+    let index = match (config.section(name), id) {
+        (Some(Value::Sequence(instances)), Some(id)) => instances
+            .iter()
+            .position(|i| i.get("id").and_then(Value::as_str) == Some(id)),
+        _ => None,
+    };
+    match index {
+        Some(i) => format!("{name}[{i}]"),
+        None => name.to_string(),
+    }
+}
+
+/// Find the line of a key path, e.g. `battery[1].name`, in the config file.
+/// This is a best effort text search, the YAML parser does not keep
+/// the location of the values
+fn locate(content: &str, path: &str) -> Option<usize> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut current = 0;
+    for segment in path.split('.') {
+        let (key, index) = match segment.split_once('[') {
+            Some((key, i)) => (key, i.trim_end_matches(']').parse::<usize>().ok()),
+            None => (segment, None),
+        };
+        current += lines[current..].iter().position(|line| {
+            let line = line.trim_start().trim_start_matches("- ");
+            line.trim_start_matches(['\'', '"'])
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start_matches(['\'', '"']).starts_with(':'))
+        })?;
+        if let Some(i) = index {
+            let items = lines[current + 1..]
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| line.trim_start().starts_with("- "));
+            current += 1 + items.map(|(n, _)| n).nth(i)?;
+        }
+    }
+    Some(current + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "format: '%a %m'
+battery:
+  - id: bat0
+    nme: BAT0
+  - id: bat1
+    name: BAT1
+memory:
+  tick: 100
+  high_levl: 90
+colour: red
+";
+
+    #[test]
+    fn find_unknown_keys() {
+        let raw: Value = serde_yaml::from_str(CONFIG).unwrap();
+        let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+        let mut unknown = vec![];
+        let known = known_config(&config, &Registry::default()).unwrap();
+        unknown_keys(&raw, &known, "", &mut unknown);
+        assert_eq!(unknown, ["battery[0].nme", "memory.high_levl", "colour"]);
+    }
+
+    #[test]
+    fn locate_module_errors() {
+        let content = "format: '%{memory:b} %{sound}'
+memory:
+  - id: a
+  - id: b
+    color: red
+sound:
+  - id: a
+  - id: b
+";
+        let problems: Vec<String> = check(content, &Registry::default())
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert!(problems.iter().any(|p| p.starts_with("line 4: [memory]")));
+        assert!(problems.iter().any(|p| p.starts_with("line 6: sound:")));
+    }
+
+    #[test]
+    fn locate_keys() {
+        assert_eq!(locate(CONFIG, "format"), Some(1));
+        assert_eq!(locate(CONFIG, "battery[0].nme"), Some(4));
+        assert_eq!(locate(CONFIG, "battery[1].name"), Some(6));
+        assert_eq!(locate(CONFIG, "memory.high_levl"), Some(9));
+        assert_eq!(locate(CONFIG, "colour"), Some(10));
+        assert_eq!(locate(CONFIG, "wireless"), None);
+    }
+}
@@ -2,8 +2,9 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize, Debug, Clone, ValueEnum)]
 pub enum Logs {
@@ -18,4 +19,45 @@ pub struct Cli {
     /// Enable app logs
     #[arg(short, long)]
     pub logs: Option<Logs>,
+
+    /// Only display the given module, e.g. battery or battery:bat1
+    #[arg(short, long)]
+    pub module: Option<String>,
+
+    /// Output the module as a waybar custom module (JSON), requires --module
+    #[arg(short, long, requires = "module")]
+    pub waybar: bool,
+
+    /// Print a single output once every module has data, then exit
+    #[arg(short, long)]
+    pub once: bool,
+
+    /// With --once, print the data of every module as JSON
+    #[arg(short, long, requires = "once")]
+    pub json: bool,
+
+    /// With --once, the maximum time in millisecond to wait for the modules
+    #[arg(short, long, default_value_t = 2000, requires = "once")]
+    pub timeout: u64,
+
+    /// The IPC socket, default: $XDG_RUNTIME_DIR/baru.sock
+    #[arg(short, long, global = true)]
+    pub socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone)]
+pub enum Command {
+    /// Check the config file, report every problem found and exit
+    Check,
+    /// Send a command to the running instance through its IPC socket:
+    /// list, get <module>, refresh [module], pause <module>,
+    /// resume <module>, reload, volume <sound|mic> <up|down|set> <percent>
+    /// or volume <sound|mic> mute. A module is given as `name` or `name:id`
+    Msg {
+        #[arg(required = true)]
+        command: Vec<String>,
+    },
 }
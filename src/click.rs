@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::error::Error;
+use serde::Deserialize;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use tracing::{debug, info, instrument, warn};
+
+/// i3bar protocol mouse buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    ScrollUp,
+    ScrollDown,
+    Other(u32),
+}
+
+impl From<u32> for Button {
+    fn from(button: u32) -> Self {
+        match button {
+            1 => Button::Left,
+            2 => Button::Middle,
+            3 => Button::Right,
+            4 => Button::ScrollUp,
+            5 => Button::ScrollDown,
+            b => Button::Other(b),
+        }
+    }
+}
+
+impl Button {
+    /// Number of the button, e.g. 1 for the left button
+    pub fn code(&self) -> u32 {
+        match self {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+            Button::ScrollUp => 4,
+            Button::ScrollDown => 5,
+            Button::Other(b) => *b,
+        }
+    }
+}
+
+/// Click event sent by the bar on stdin, see
+/// https://i3wm.org/docs/i3bar-protocol.html#_click_events
+#[derive(Debug, Deserialize)]
+pub struct ClickEvent {
+    pub name: String,
+    pub instance: Option<String>,
+    button: u32,
+}
+
+impl ClickEvent {
+    pub fn button(&self) -> Button {
+        Button::from(self.button)
+    }
+}
+
+/// Read the click events from stdin in a dedicated thread
+#[instrument]
+pub fn listen() -> Result<Receiver<ClickEvent>, Error> {
+    let (tx, rx) = mpsc::channel();
+    let builder = thread::Builder::new().name("click_events".into());
+    builder.spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(event) = parse_event(&line) {
+                debug!("{:?}", event);
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+        info!("click events stream closed");
+    })?;
+    Ok(rx)
+}
+
+/// The bar sends an endless JSON array, one event per line:
+/// the first line opens the array and the next events
+/// are prefixed with a comma
+fn parse_event(line: &str) -> Option<ClickEvent> {
+    let event = line.trim().trim_start_matches([',', '[']).trim();
+    if event.is_empty() {
+        return None;
+    }
+    serde_json::from_str(event)
+        .inspect_err(|e| warn!("invalid click event `{}`: {}", event, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_array_opening() {
+        assert!(parse_event("[").is_none());
+        assert!(parse_event("").is_none());
+    }
+
+    #[test]
+    fn parse_first_event() {
+        let event = parse_event(r#"{"name":"sound","button":1,"x":10,"y":2}"#).unwrap();
+        assert_eq!(event.name, "sound");
+        assert_eq!(event.instance, None);
+        assert_eq!(event.button(), Button::Left);
+    }
+
+    #[test]
+    fn parse_next_event() {
+        let event = parse_event(r#",{"name":"battery","instance":"bat1","button":5}"#).unwrap();
+        assert_eq!(event.name, "battery");
+        assert_eq!(event.instance.as_deref(), Some("bat1"));
+        assert_eq!(event.button(), Button::ScrollDown);
+    }
+
+    #[test]
+    fn parse_invalid_event() {
+        assert!(parse_event(",{\"button\":1}").is_none());
+    }
+}
@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Control of a module thread, shared with the core.
+/// The module sleeps on it between two updates so it can be
+/// woken up, paused or stopped at any time.
+#[derive(Debug)]
+pub struct Control {
+    running: Arc<AtomicBool>,
+    state: Mutex<ControlState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    wake: bool,
+    paused: bool,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Control::new()
+    }
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Control {
+            running: Arc::new(AtomicBool::new(true)),
+            state: Mutex::new(ControlState::default()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// The running state, for the callbacks which can't borrow the
+    /// control, e.g. the helpers of a script
+    pub fn running(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.running)
+    }
+
+    /// Sleep for the given duration, until woken up or stopped.
+    /// While paused, sleep until resumed
+    pub fn sleep(&self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        let mut state = self.lock();
+        loop {
+            if !self.is_running() {
+                return;
+            }
+            if state.wake && !state.paused {
+                state.wake = false;
+                return;
+            }
+            let timeout = match state.paused {
+                true => None,
+                false => match deadline.checked_duration_since(Instant::now()) {
+                    Some(t) if !t.is_zero() => Some(t),
+                    _ => return,
+                },
+            };
+            state = match timeout {
+                Some(t) => {
+                    self.condvar
+                        .wait_timeout(state, t)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.condvar.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+
+    /// Wake up the module, it updates right away
+    pub fn wake(&self) {
+        self.lock().wake = true;
+        self.condvar.notify_all();
+    }
+
+    pub fn pause(&self) {
+        self.lock().paused = true;
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.lock();
+        state.paused = false;
+        state.wake = true;
+        self.condvar.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    /// Stop the module, without waiting for the end of its sleep
+    pub fn stop(&self) {
+        let _state = self.lock();
+        self.running.store(false, Ordering::Relaxed);
+        self.condvar.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ControlState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn wake_up_sleep() {
+        let control = Arc::new(Control::new());
+        let c = Arc::clone(&control);
+        let start = Instant::now();
+        let handle = thread::spawn(move || c.sleep(Duration::from_secs(10)));
+        thread::sleep(Duration::from_millis(20));
+        control.wake();
+        handle.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn sleep_after_wake_up() {
+        let control = Control::new();
+        control.wake();
+        // the pending wake-up is consumed by the first sleep only
+        control.sleep(Duration::from_secs(10));
+        let start = Instant::now();
+        control.sleep(Duration::from_millis(20));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,319 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::error::Error;
+use crate::pulse::{self, CommandKind, PulseCommand};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
+
+const XDG_RUNTIME_DIR: &str = "XDG_RUNTIME_DIR";
+const SOCKET_FILE: &str = "baru.sock";
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Command received on the socket, one per connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// List the modules and the state of their thread
+    List,
+    /// Get the current data of a module
+    Get(Target),
+    /// Update a module, or every module, right away
+    Refresh(Option<Target>),
+    /// Stop updating a module until resumed
+    Pause(Target),
+    Resume(Target),
+    /// Reload the config file
+    Reload,
+    /// Change the volume of the sink or the source of the sound/mic module
+    Volume(PulseCommand),
+}
+
+/// Module targeted by a request, e.g. `battery` or `battery:bat1`.
+/// Without instance id, every instance of the module is targeted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub instance: Option<String>,
+}
+
+/// Reply sent back to the client, as a JSON object
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+/// Request waiting for its reply from the main loop
+#[derive(Debug)]
+pub struct Message {
+    pub request: Request,
+    reply: Sender<Reply>,
+}
+
+/// Socket listener, the socket file is removed once dropped
+#[derive(Debug)]
+pub struct Server {
+    path: PathBuf,
+    messages: Receiver<Message>,
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, instance) = match s.split_once(':') {
+            Some((name, id)) => (name, Some(id.to_string())),
+            None => (s, None),
+        };
+        if name.is_empty() || instance.as_deref() == Some("") {
+            return Err(Error::new(format!("invalid module \"{s}\"")));
+        }
+        Ok(Target {
+            name: name.to_string(),
+            instance,
+        })
+    }
+}
+
+impl FromStr for Request {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        if command == "volume" {
+            return parse_volume(&words.collect::<Vec<_>>());
+        }
+        let target = words.next().map(Target::from_str).transpose()?;
+        if let Some(extra) = words.next() {
+            return Err(Error::new(format!("unexpected argument \"{extra}\"")));
+        }
+        let required = |target: Option<Target>| {
+            target.ok_or_else(|| Error::new(format!("\"{command}\" requires a module")))
+        };
+        match command {
+            "list" => Ok(Request::List),
+            "get" => Ok(Request::Get(required(target)?)),
+            "refresh" => Ok(Request::Refresh(target)),
+            "pause" => Ok(Request::Pause(required(target)?)),
+            "resume" => Ok(Request::Resume(required(target)?)),
+            "reload" => Ok(Request::Reload),
+            c => Err(Error::new(format!("unknown command \"{c}\""))),
+        }
+    }
+}
+
+/// Parse the arguments of `volume <sound|mic> <up|down|set> <percent>`
+/// or `volume <sound|mic> mute`
+fn parse_volume(args: &[&str]) -> Result<Request, Error> {
+    let usage = || {
+        Error::new("expected volume <sound|mic> <up|down|set> <percent> or volume <sound|mic> mute")
+    };
+    let [device, action, value @ ..] = args else {
+        return Err(usage());
+    };
+    let target = match *device {
+        "sound" => pulse::Target::Sink,
+        "mic" => pulse::Target::Source,
+        d => {
+            return Err(Error::new(format!(
+                "unknown device \"{d}\", expected sound or mic"
+            )));
+        }
+    };
+    let percent = |value: &str| {
+        value
+            .trim_end_matches('%')
+            .parse::<u32>()
+            .map_err(|_| Error::new(format!("invalid volume \"{value}\"")))
+    };
+    let (kind, value) = match (*action, value) {
+        ("up", [v]) => (CommandKind::VolumeUp, percent(v)?),
+        ("down", [v]) => (CommandKind::VolumeDown, percent(v)?),
+        ("set", [v]) => match percent(v)? {
+            // the volume set is clamped to 100% by the audio lib
+            p if p > 100 => return Err(Error::new(format!("volume {p}% above 100%"))),
+            p => (CommandKind::SetVolume, p),
+        },
+        ("mute", []) => (CommandKind::ToggleMute, 0),
+        _ => return Err(usage()),
+    };
+    Ok(Request::Volume(PulseCommand(target, kind, value)))
+}
+
+impl Message {
+    pub fn reply(self, reply: Reply) {
+        // the client may have given up
+        self.reply.send(reply).ok();
+    }
+}
+
+impl Server {
+    /// The pending requests
+    pub fn try_iter(&self) -> impl Iterator<Item = Message> + '_ {
+        self.messages.try_iter()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// The default socket path, `$XDG_RUNTIME_DIR/baru.sock`
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os(XDG_RUNTIME_DIR).map(|dir| Path::new(&dir).join(SOCKET_FILE))
+}
+
+/// Listen on the socket in a dedicated thread, the requests are
+/// then handled by the main loop
+#[instrument]
+pub fn listen(path: &Path) -> Result<Server, Error> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::new(format!(
+                "socket {} already in use by another instance",
+                path.display()
+            )));
+        }
+        // left by an instance which did not exit cleanly
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let (tx, rx) = mpsc::channel();
+    let builder = thread::Builder::new().name("ipc".into());
+    builder.spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(Error::from)
+                .and_then(|s| handle_client(s, &tx));
+            match result {
+                Ok(true) => {}
+                // the main loop has stopped
+                Ok(false) => break,
+                Err(e) => warn!("ipc client failed: {}", e),
+            }
+        }
+        info!("ipc listener closed");
+    })?;
+    info!("listening on {}", path.display());
+    Ok(Server {
+        path: path.to_path_buf(),
+        messages: rx,
+    })
+}
+
+/// Read the request of a client, wait for its reply and send it back.
+/// Returns false once the main loop no longer takes requests
+fn handle_client(stream: UnixStream, tx: &Sender<Message>) -> Result<bool, Error> {
+    // an idle client must not block the next ones
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    stream.set_write_timeout(Some(REPLY_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    debug!("ipc request `{}`", line.trim());
+    let mut open = true;
+    let reply = match Request::from_str(&line) {
+        Ok(request) => {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let message = Message {
+                request,
+                reply: reply_tx,
+            };
+            open = tx.send(message).is_ok();
+            reply_rx
+                .recv_timeout(REPLY_TIMEOUT)
+                .unwrap_or_else(|_| Reply::Error("no reply from baru".to_string()))
+        }
+        Err(e) => Reply::Error(e.to_string()),
+    };
+    let mut stream = stream;
+    writeln!(stream, "{}", serde_json::to_string(&reply)?)?;
+    Ok(open)
+}
+
+/// Send a request to a running instance and get its reply
+#[instrument]
+pub fn send(path: &Path, request: &str) -> Result<Reply, Error> {
+    let mut stream = UnixStream::connect(path)
+        .map_err(|e| Error::new(format!("failed to connect to {}: {}", path.display(), e)))?;
+    writeln!(stream, "{}", request)?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(Request::from_str("list").unwrap(), Request::List);
+        assert_eq!(Request::from_str(" reload\n").unwrap(), Request::Reload);
+        assert_eq!(
+            Request::from_str("refresh").unwrap(),
+            Request::Refresh(None)
+        );
+        assert_eq!(
+            Request::from_str("get battery:bat1").unwrap(),
+            Request::Get(Target {
+                name: "battery".to_string(),
+                instance: Some("bat1".to_string()),
+            })
+        );
+        assert_eq!(
+            Request::from_str("pause brightness").unwrap(),
+            Request::Pause(Target {
+                name: "brightness".to_string(),
+                instance: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_invalid_requests() {
+        assert!(Request::from_str("").is_err());
+        assert!(Request::from_str("stop").is_err());
+        assert!(Request::from_str("get").is_err());
+        assert!(Request::from_str("get battery:").is_err());
+        assert!(Request::from_str("pause sound mic").is_err());
+    }
+
+    #[test]
+    fn parse_volume_requests() {
+        assert_eq!(
+            Request::from_str("volume sound up 5%").unwrap(),
+            Request::Volume(PulseCommand(pulse::Target::Sink, CommandKind::VolumeUp, 5))
+        );
+        assert_eq!(
+            Request::from_str("volume mic mute").unwrap(),
+            Request::Volume(PulseCommand(
+                pulse::Target::Source,
+                CommandKind::ToggleMute,
+                0
+            ))
+        );
+        assert!(Request::from_str("volume sound").is_err());
+        assert!(Request::from_str("volume sound set").is_err());
+        assert!(Request::from_str("volume sound set x").is_err());
+        assert!(Request::from_str("volume sound set 100").is_ok());
+        assert!(Request::from_str("volume sound set 150").is_err());
+        assert!(Request::from_str("volume sound mute 5").is_err());
+        assert!(Request::from_str("volume speaker up 5").is_err());
+    }
+}
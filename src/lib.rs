@@ -2,154 +2,643 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+mod action;
+pub mod check;
 pub mod cli;
+mod click;
+mod control;
 mod error;
 mod http;
+pub mod ipc;
 mod module;
 mod modules;
 mod netlink;
+mod output;
+pub mod plugin;
+pub mod protocol;
 mod pulse;
 pub mod signal;
 pub mod trace;
 pub mod util;
+mod value;
 
 use anyhow::{Result, anyhow};
-use error::Error;
-use module::{Bar, ModuleData};
-use modules::battery::Config as BatteryConfig;
-use modules::brightness::Config as BrightnessConfig;
-use modules::cpu_freq::Config as CpuFreqConfig;
-use modules::cpu_usage::Config as CpuUsageConfig;
-use modules::date_time::Config as DateTimeConfig;
-use modules::memory::Config as MemoryConfig;
-use modules::mic::Config as MicConfig;
-use modules::sound::Config as SoundConfig;
-use modules::temperature::Config as TemperatureConfig;
-use modules::weather::Config as WeatherConfig;
-use modules::wired::Config as WiredConfig;
-use modules::wireless::Config as WirelessConfig;
+use click::ClickEvent;
+use ipc::{Reply, Request, Target};
+use module::{Instances, ModuleData};
 use once_cell::sync::Lazy;
+use output::{Backend, Output};
+use protocol::{Block, Header, Protocol, Snapshot, WaybarOutput};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::AtomicBool;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
 use std::thread::JoinHandle;
-use tracing::{error, info, instrument};
+use std::time::{Duration, Instant};
+use tracing::{error, info, instrument, warn};
 
-// Global application state, used to terminate the main-loop and all modules
+pub use control::Control;
+pub use error::Error;
+pub use module::{CommonConfig, Instance, Level, Module, ModuleSender, Registry};
+pub use value::{Data, Number, State, Value};
+
+const TICK_RATE: Duration = Duration::from_millis(50);
+const MIN_INTERVAL: Duration = Duration::ZERO;
+// maximum time given to the modules to stop on exit
+const CLEANUP_TIMEOUT: Duration = Duration::from_millis(300);
+/// Options of the root config, not available as module names
+const ROOT_OPTIONS: [&str; 8] = [
+    "format",
+    "tick",
+    "min_interval",
+    "watch_config",
+    "protocol",
+    "output",
+    "failed_icon",
+    "pulse_tick",
+];
+
+// Global application state, used to terminate the main-loop and the pulse module
 pub static RUN: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(true));
+// Set on SIGHUP, used to reload the configuration
+pub static RELOAD: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
+/// Real-time signals received, bit `n` is set by `SIGRTMIN+n`
+pub static WAKE: Lazy<AtomicU64> = Lazy::new(|| AtomicU64::new(0));
 
 #[derive(Debug)]
 /// Message sent by modules.
-/// `0`: module key,
-/// `1`: value,
-/// `2`: label
-pub struct ModuleMsg(char, Option<String>, Option<String>);
+/// `0`: module index,
+/// `1`: module data
+pub struct ModuleMsg(usize, Data);
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     format: String,
-    pub tick: Option<u32>,
+    tick: Option<u32>,
+    min_interval: Option<u32>,
+    watch_config: Option<bool>,
+    protocol: Option<Protocol>,
+    output: Option<Output>,
     failed_icon: Option<String>,
     pulse_tick: Option<u32>,
-    battery: Option<BatteryConfig>,
-    brightness: Option<BrightnessConfig>,
-    cpu_usage: Option<CpuUsageConfig>,
-    cpu_freq: Option<CpuFreqConfig>,
-    date_time: Option<DateTimeConfig>,
-    memory: Option<MemoryConfig>,
-    mic: Option<MicConfig>,
-    sound: Option<SoundConfig>,
-    temperature: Option<TemperatureConfig>,
-    weather: Option<WeatherConfig>,
-    wired: Option<WiredConfig>,
-    wireless: Option<WirelessConfig>,
+    /// Config sections of the modules, by module name
+    #[serde(flatten)]
+    modules: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl Config {
+    /// Warn about the changes of the new config needing a restart of baru,
+    /// the PulseAudio thread reading its options once
+    pub fn warn_restart(&self, new: &Config) {
+        if pulse::PULSE.get().is_some()
+            && pulse::Settings::new(self).ok() != pulse::Settings::new(new).ok()
+        {
+            warn!("pulse_tick, sink_name and source_name changed, restart baru to apply them");
+        }
+    }
+
+    /// Only display the module of the given name, e.g. `battery`
+    /// or `battery:bat1`
+    pub fn select_module(&mut self, name: &str) -> Result<()> {
+        name.parse::<Target>()?;
+        self.format = format!("%{{{name}}}");
+        Ok(())
+    }
+
+    /// The config section of a module, e.g. `battery`
+    fn section(&self, name: &str) -> Option<&serde_yaml::Value> {
+        self.modules.get(name)
+    }
+
+    /// The instances declared in the config section of a module
+    fn instances<T: DeserializeOwned>(&self, name: &str) -> Result<Instances<T>, Error> {
+        Instances::from_section(self.section(name))
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol.unwrap_or_default()
+    }
+
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = Some(protocol);
+    }
+
+    /// The markup dialect of the output, plain with the i3bar protocol
+    /// which has its own block colors
+    fn output(&self) -> Output {
+        match self.protocol() {
+            Protocol::I3bar => Output::Plain,
+            _ => self.output.unwrap_or_default(),
+        }
+    }
+
+    /// Whether the config file is watched to reload the config on change
+    pub fn watch_config(&self) -> bool {
+        self.watch_config.unwrap_or(false)
+    }
+}
+
+/// Output state kept across the configuration reloads
+#[derive(Default)]
+pub struct Session {
+    header_sent: bool,
+    last_output: Option<String>,
+    last_print: Option<Instant>,
+    click_events: Option<Receiver<ClickEvent>>,
+    pulse: Option<JoinHandle<Result<(), Error>>>,
+    ipc: Option<ipc::Server>,
+    /// Last data of the modules stopped by a reload
+    carried: Vec<CarriedData>,
+}
+
+/// Last data of a module instance, restored after a reload if the config
+/// section of the module is unchanged
+struct CarriedData {
+    name: String,
+    instance: Option<String>,
+    section: Option<serde_yaml::Value>,
+    data: (Data, Option<usize>),
+}
+
+impl Session {
+    /// Take the requests sent on the given socket, see [`ipc`]
+    pub fn listen(&mut self, path: &Path) -> Result<(), Error> {
+        self.ipc = Some(ipc::listen(path)?);
+        Ok(())
+    }
 }
 
 pub struct Baru<'a> {
     config: &'a Config,
-    modules: Vec<ModuleData<'a>>,
+    modules: Vec<ModuleData>,
     format: &'a str,
+    protocol: Protocol,
+    backend: &'static dyn Backend,
     markup_matches: Vec<MarkupMatch>,
+    groups: Vec<Group>,
     channel: (Sender<ModuleMsg>, Receiver<ModuleMsg>),
-    pulse: Option<JoinHandle<Result<(), Error>>>,
+    tick: Duration,
+    min_interval: Duration,
+    session: Session,
 }
 
 #[derive(Debug)]
-struct MarkupMatch(char, usize);
+/// Markup found in the root format.
+/// `0`: module name,
+/// `1`: index of the key,
+/// `2`: instance id,
+/// `3`: end index of the markup, exclusive
+struct MarkupMatch(String, usize, Option<String>, usize);
+
+#[derive(Debug, PartialEq, Eq)]
+/// Conditional segment of the root format, e.g. `%[ | %e]`.
+/// `0`: start index of the segment,
+/// `1`: end index of the segment, exclusive
+struct Group(usize, usize);
+
+impl Group {
+    fn contains(&self, markup: &MarkupMatch) -> bool {
+        self.0 < markup.1 && markup.3 <= self.1
+    }
+}
 
 impl<'a> Baru<'a> {
     #[instrument(skip_all)]
     pub fn with_config(config: &'a Config) -> Result<Self> {
-        let mut modules = vec![];
-        let markup_matches = parse_format(&config.format);
+        Baru::with_session(config, Session::default())
+    }
+
+    /// Create an instance which takes over the output of a previous one,
+    /// see `stop`
+    #[instrument(skip_all)]
+    pub fn with_session(config: &'a Config, session: Session) -> Result<Self> {
+        Baru::with_registry(config, &Registry::default(), session)
+    }
+
+    /// Create an instance using the modules of the given registry,
+    /// e.g. the built-in modules and third-party ones
+    #[instrument(skip_all)]
+    pub fn with_registry(
+        config: &'a Config,
+        registry: &Registry,
+        mut session: Session,
+    ) -> Result<Self> {
+        let mut modules: Vec<ModuleData> = vec![];
+        pulse::check(config).map_err(|(_, e)| e)?;
+        let markup_matches = parse_format(&config.format, registry)?;
+        let groups = parse_groups(&config.format)?;
         for markup in &markup_matches {
-            modules.push(ModuleData::new(markup.0, config)?);
+            let instance = markup.2.as_deref();
+            if modules.iter().any(|m| m.is(&markup.0, instance)) {
+                continue;
+            }
+            let mut module = ModuleData::new(&markup.0, instance, config, registry)?;
+            let carried = session.carried.iter().find(|c| {
+                c.name == markup.0
+                    && c.instance.as_deref() == instance
+                    && c.section.as_ref() == config.section(&markup.0)
+            });
+            if let Some(c) = carried {
+                module.restore_data(c.data.clone());
+            }
+            modules.push(module);
         }
+        session.carried.clear();
         Ok(Baru {
             config,
             channel: mpsc::channel(),
             modules,
             format: &config.format,
+            protocol: config.protocol.unwrap_or_default(),
+            backend: config.output().backend(),
             markup_matches,
-            pulse: None,
+            groups,
+            tick: config
+                .tick
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or(TICK_RATE),
+            min_interval: config
+                .min_interval
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or(MIN_INTERVAL),
+            session,
         })
     }
 
     #[instrument(skip_all)]
     pub fn start(&mut self) -> Result<()> {
         // check if any module needs pulse, i.e. sound or mic modules
-        let need_pulse = self.modules.iter().any(|m| m.key == 's' || m.key == 'i');
-        if need_pulse {
-            self.pulse = Some(pulse::init(self.config)?);
-        }
-        for data in &mut self.modules {
-            let builder = thread::Builder::new().name(format!("mod_{}", data.module.name()));
-            let cloned_m_conf = self.config.clone();
-            let tx1 = mpsc::Sender::clone(&self.channel.0);
-            let run = data.module.run_fn();
-            let key = data.key;
-            let c_name = data.module.name().to_string();
-            let handle = builder.spawn(move || -> Result<(), Error> {
-                run(&RUN, key, cloned_m_conf, tx1)
-                    .inspect_err(|e| error!("[{}] module failed: {}", c_name, e))?;
-                info!("[{}] module stopped", c_name);
-                Ok(())
-            })?;
+        let need_pulse = self
+            .modules
+            .iter()
+            .any(|m| matches!(m.module.name(), "sound" | "mic"));
+        if need_pulse && pulse::PULSE.get().is_none() {
+            self.session.pulse = Some(pulse::init(self.config)?);
+        }
+        for index in 0..self.modules.len() {
+            let handle = self.spawn(index)?;
+            let data = &mut self.modules[index];
             data.start(handle);
             info!("[{}] module started", data.module.name());
         }
+        if self.protocol == Protocol::I3bar && !self.session.header_sent {
+            // i3bar protocol, send the header and open the endless array
+            println!("{}", serde_json::to_string(&Header::default())?);
+            println!("[");
+            self.session.header_sent = true;
+        }
+        if self.protocol == Protocol::I3bar && self.session.click_events.is_none() {
+            self.session.click_events = Some(click::listen()?);
+        }
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    fn module_output(&self, key: char) -> Result<&str> {
-        let module = self
+    /// Run a module in its own thread
+    fn spawn(&self, index: usize) -> Result<JoinHandle<Result<(), Error>>> {
+        let data = &self.modules[index];
+        let builder = thread::Builder::new().name(format!("mod_{}", data.module.name()));
+        let tx = ModuleSender::new(index, mpsc::Sender::clone(&self.channel.0));
+        let run = data.module.runner();
+        let c_name = data.module.name().to_string();
+        let control = Arc::clone(data.control());
+        let handle = builder.spawn(move || -> Result<(), Error> {
+            run(&control, &tx)
+                // once stopped, the channel may be closed
+                .or_else(|e| match control.is_running() {
+                    true => Err(e),
+                    false => Ok(()),
+                })
+                .inspect_err(|e| error!("[{}] module failed: {}", c_name, e))?;
+            info!("[{}] module stopped", c_name);
+            Ok(())
+        })?;
+        Ok(handle)
+    }
+
+    /// Restart the failed modules whose restart delay is over
+    fn restart_failed(&mut self) {
+        for index in 0..self.modules.len() {
+            if !self.modules[index].restart_due() {
+                continue;
+            }
+            match self.spawn(index) {
+                Ok(handle) => self.modules[index].restart(handle),
+                Err(e) => error!(
+                    "[{}] failed to restart module: {}",
+                    self.modules[index].module.name(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Stop the modules of this instance and give back the session
+    /// to be taken over by a new instance, e.g. on config reload
+    #[instrument(skip_all)]
+    pub fn stop(mut self) -> Session {
+        self.stop_modules();
+        self.join_modules();
+        info!("modules stopped");
+        self.session.carried = self
             .modules
             .iter()
-            .find(|data| data.key == key)
-            .ok_or(anyhow!("module for key \"{}\" not found", key))?;
-        Ok(module.output())
+            .filter_map(|m| {
+                let name = m.module.name();
+                Some(CarriedData {
+                    name: name.to_string(),
+                    instance: m.instance.clone(),
+                    section: self.config.section(name).cloned(),
+                    data: m.last_data()?,
+                })
+            })
+            .collect();
+        self.session
+    }
+
+    /// Wait for the threads of the stopped modules, at most
+    /// `CLEANUP_TIMEOUT`, and join them
+    fn join_modules(&mut self) {
+        // give the modules a chance to clean up, e.g. to terminate their command
+        let deadline = Instant::now() + CLEANUP_TIMEOUT;
+        while self.modules.iter().any(|m| m.is_alive()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        for module in &mut self.modules {
+            if module.is_alive() {
+                warn!("[{}] module still running", module.module.name());
+            } else {
+                module.update_state().ok();
+            }
+        }
+    }
+
+    fn stop_modules(&self) {
+        for data in &self.modules {
+            data.control().stop();
+        }
+    }
+
+    #[instrument(skip(self))]
+    fn module(&self, markup: &MarkupMatch) -> Result<&ModuleData> {
+        self.modules
+            .iter()
+            .find(|data| data.is(&markup.0, markup.2.as_deref()))
+            .ok_or(anyhow!("module \"{}\" not found", markup.0))
     }
 
+    /// Wait for the modules messages, at most `tick`, then print the
+    /// output if it has changed.
+    /// Once something was printed, the next output is deferred until
+    /// `min_interval` has elapsed, coalescing the messages received meanwhile.
     #[instrument(skip(self))]
     pub fn update(&mut self) -> Result<()> {
-        let messages: Vec<ModuleMsg> = self.channel.1.try_iter().collect();
-        for module in &mut self.modules {
+        self.receive();
+        if self.throttled().is_some() {
+            return Ok(());
+        }
+        let output = self.render()?;
+        if self.session.last_output.as_ref() == Some(&output) {
+            return Ok(());
+        }
+        if self.protocol == Protocol::I3bar && self.session.last_output.is_some() {
+            // items of the endless array are separated by a comma
+            print!(",");
+        }
+        println!("{}", output);
+        self.session.last_output = Some(output);
+        self.session.last_print = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Wait until every module has sent its first data or has stopped,
+    /// at most `timeout`
+    #[instrument(skip(self))]
+    pub fn wait_ready(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while !self.is_ready() && Instant::now() < deadline {
+            self.receive();
+        }
+        if !self.is_ready() {
+            warn!("timeout, some modules have no data");
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.modules.iter().all(|m| m.is_ready())
+    }
+
+    /// The time left before the next output can be printed, if any
+    fn throttled(&self) -> Option<Duration> {
+        let elapsed = self.session.last_print?.elapsed();
+        self.min_interval
+            .checked_sub(elapsed)
+            .filter(|left| !left.is_zero())
+    }
+
+    /// Wait for the modules messages, at most `tick`,
+    /// and update the modules
+    fn receive(&mut self) {
+        // join the finished modules first, so the data they sent before
+        // exiting is read below and not taken for a module without data
+        for module in self.modules.iter_mut() {
             module.update_state().ok();
+        }
+        let mut messages = vec![];
+        // wake up in time to print a deferred output
+        let timeout = self
+            .throttled()
+            .map_or(self.tick, |left| left.min(self.tick));
+        if let Ok(message) = self.channel.1.recv_timeout(timeout) {
+            messages.push(message);
+        }
+        messages.extend(self.channel.1.try_iter());
+        if let Some(events) = &self.session.click_events {
+            let events: Vec<ClickEvent> = events.try_iter().collect();
+            for event in events {
+                self.on_click(event);
+            }
+        }
+        let signals = WAKE.swap(0, Ordering::Relaxed);
+        for module in &self.modules {
+            if let Some(n) = module.signal()
+                && signals & (1 << n) != 0
+            {
+                module.control().wake();
+            }
+        }
+        if let Some(server) = &self.session.ipc {
+            let messages: Vec<ipc::Message> = server.try_iter().collect();
+            for message in messages {
+                let reply = self.on_request(&message.request);
+                message.reply(reply);
+            }
+        }
+        for (index, module) in self.modules.iter_mut().enumerate() {
             let mut iter = messages.iter().rev();
-            let message = iter.find(|v| v.0 == module.key);
+            let message = iter.find(|v| v.0 == index);
             if let Some(value) = message {
-                module.new_data(value.1.as_deref(), value.2.as_deref());
+                module.new_data(&value.1);
+            }
+        }
+        self.restart_failed();
+    }
+
+    /// Render the output of the current protocol
+    pub fn render(&self) -> Result<String> {
+        match self.protocol {
+            Protocol::Plain => self.render_format(),
+            Protocol::I3bar => self.render_blocks(),
+            Protocol::Waybar => self.render_waybar(),
+        }
+    }
+
+    /// Render the current data of every module as a JSON array
+    pub fn render_json(&self) -> Result<String> {
+        let snapshots: Vec<Snapshot> = self.modules.iter().map(|m| m.snapshot()).collect();
+        Ok(serde_json::to_string(&snapshots)?)
+    }
+
+    #[instrument(skip(self))]
+    fn on_request(&self, request: &Request) -> Reply {
+        let targets = |target: &Target| -> Result<Vec<&ModuleData>, String> {
+            let modules: Vec<&ModuleData> = self
+                .modules
+                .iter()
+                .filter(|m| m.matches(&target.name, target.instance.as_deref()))
+                .collect();
+            match modules.is_empty() {
+                true => Err(format!("module \"{}\" not found", target.name)),
+                false => Ok(modules),
+            }
+        };
+        let result = match request {
+            Request::List => {
+                let status: Vec<_> = self.modules.iter().map(|m| m.status()).collect();
+                serde_json::to_value(status).map_err(|e| e.to_string())
+            }
+            Request::Get(target) => targets(target).and_then(|modules| {
+                let snapshots: Vec<_> = modules.iter().map(|m| m.snapshot()).collect();
+                serde_json::to_value(snapshots).map_err(|e| e.to_string())
+            }),
+            Request::Refresh(None) => {
+                self.modules.iter().for_each(|m| m.control().wake());
+                Ok(serde_json::Value::Null)
+            }
+            Request::Refresh(Some(target)) => targets(target).map(|modules| {
+                modules.iter().for_each(|m| m.control().wake());
+                serde_json::Value::Null
+            }),
+            Request::Pause(target) => targets(target).map(|modules| {
+                modules.iter().for_each(|m| m.control().pause());
+                serde_json::Value::Null
+            }),
+            Request::Resume(target) => targets(target).map(|modules| {
+                modules.iter().for_each(|m| m.control().resume());
+                serde_json::Value::Null
+            }),
+            Request::Reload => {
+                info!("reload requested");
+                RELOAD.store(true, Ordering::Relaxed);
+                Ok(serde_json::Value::Null)
+            }
+            Request::Volume(_) if pulse::PULSE.get().is_none() => {
+                Err("no sound or mic module running".to_string())
+            }
+            Request::Volume(command) => pulse::send_command(*command)
+                .map(|_| serde_json::Value::Null)
+                .map_err(|e| e.to_string()),
+        };
+        match result {
+            Ok(value) => Reply::Ok(value),
+            Err(e) => Reply::Error(e),
+        }
+    }
+
+    #[instrument(skip(self))]
+    fn on_click(&mut self, event: ClickEvent) {
+        let module = self
+            .modules
+            .iter_mut()
+            .find(|data| data.module.name() == event.name && data.instance == event.instance);
+        match module {
+            Some(data) => data.on_click(event.button()),
+            None => warn!(
+                "click event for unknown module \"{}\" (instance {:?})",
+                event.name, event.instance
+            ),
+        }
+    }
+
+    /// Replace the markups of the root format by the modules output
+    fn render_format(&self) -> Result<String> {
+        let mut replacements: Vec<(Range<usize>, String)> = vec![];
+        for group in &self.groups {
+            if self.is_dropped(group)? {
+                replacements.push((group.0..group.1, String::new()));
+            } else {
+                // only remove the delimiters `%[` and `]`
+                replacements.push((group.0..group.0 + 2, String::new()));
+                replacements.push((group.1 - 1..group.1, String::new()));
+            }
+        }
+        for v in &self.markup_matches {
+            if !replacements.iter().any(|(range, _)| range.contains(&v.1)) {
+                replacements.push((v.1 - 1..v.3, self.module(v)?.styled(self.backend)));
             }
         }
+        replacements.sort_by_key(|(range, _)| range.start);
         let mut output = self.format.to_string();
-        for v in self.markup_matches.iter().rev() {
-            output.replace_range(v.1 - 1..v.1 + 1, self.module_output(v.0)?);
+        for (range, replacement) in replacements.into_iter().rev() {
+            output.replace_range(range, &replacement);
         }
-        output = output.replace("\\%", "%");
-        println!("{}", output);
-        Ok(())
+        Ok(output.replace("\\%", "%"))
+    }
+
+    /// Whether a conditional segment is dropped, i.e. all its modules are
+    /// empty. A segment without module is kept
+    fn is_dropped(&self, group: &Group) -> Result<bool> {
+        let mut markups = self.markup_matches.iter().filter(|m| group.contains(m));
+        let mut dropped = false;
+        for markup in &mut markups {
+            if !self.module(markup)?.is_empty() {
+                return Ok(false);
+            }
+            dropped = true;
+        }
+        Ok(dropped)
+    }
+
+    /// Build one i3bar status line, each module becomes its own block.
+    /// The text of the root format outside of the markups is ignored,
+    /// the modules of dropped conditional segments are skipped.
+    fn render_blocks(&self) -> Result<String> {
+        let mut blocks: Vec<Block> = vec![];
+        for markup in &self.markup_matches {
+            let group = self.groups.iter().find(|g| g.contains(markup));
+            if let Some(g) = group
+                && self.is_dropped(g)?
+            {
+                continue;
+            }
+            blocks.push(self.module(markup)?.block());
+        }
+        Ok(serde_json::to_string(&blocks)?)
+    }
+
+    /// Build the waybar custom module JSON output of the first module
+    /// of the root format
+    fn render_waybar(&self) -> Result<String> {
+        let markup = self
+            .markup_matches
+            .first()
+            .ok_or(anyhow!("no module to display"))?;
+        let output: WaybarOutput = self.module(markup)?.waybar();
+        Ok(serde_json::to_string(&output)?)
     }
 
     #[instrument(skip(self))]
@@ -159,7 +648,9 @@ impl<'a> Baru<'a> {
 
     #[instrument(skip_all)]
     pub fn cleanup(&mut self) {
-        if let Some(pulse) = self.pulse.take() {
+        self.stop_modules();
+        self.join_modules();
+        if let Some(pulse) = self.session.pulse.take() {
             match pulse.join() {
                 Ok(Ok(_)) => info!("pulse module terminated"),
                 Ok(Err(e)) => error!("pulse module failed: {}", e),
@@ -169,97 +660,577 @@ impl<'a> Baru<'a> {
     }
 }
 
-#[instrument]
-fn parse_format(format: &str) -> Vec<MarkupMatch> {
+#[instrument(skip(registry))]
+fn parse_format(format: &str, registry: &Registry) -> Result<Vec<MarkupMatch>, Error> {
     let mut matches = vec![];
     let mut iter = format.char_indices().peekable();
     while let Some((i, c)) = iter.next() {
-        if c == '%' && (i == 0 || &format[i - 1..i] != "\\") {
-            if let Some(val) = iter.peek() {
-                matches.push(MarkupMatch(val.1, val.0));
+        if c == '%'
+            && !format[..i].ends_with('\\')
+            && let Some(&(key_index, key)) = iter.peek()
+        {
+            if key == '[' {
+                // start of a conditional segment, see `parse_groups`
+                continue;
+            }
+            if key == '{' {
+                let markup = parse_named_markup(format, key_index, registry)?;
+                // skip the whole markup
+                while iter.next_if(|&(j, _)| j < markup.3).is_some() {}
+                matches.push(markup);
+                continue;
+            }
+            let name = registry
+                .name(key)
+                .ok_or_else(|| Error::new(format!("unknown markup \"{key}\"")))?
+                .to_string();
+            let end = key_index + key.len_utf8();
+            match parse_instance(&format[end..]) {
+                Some((instance, len)) => {
+                    matches.push(MarkupMatch(name, key_index, Some(instance), end + len));
+                    // skip the key and the instance id
+                    while iter.next_if(|&(j, _)| j < end + len).is_some() {}
+                }
+                None => matches.push(MarkupMatch(name, key_index, None, end)),
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Parse the conditional segments of a format, `%[...]`.
+/// A segment ends at the first `]`, segments can't be nested
+#[instrument]
+fn parse_groups(format: &str) -> Result<Vec<Group>, Error> {
+    let mut groups = vec![];
+    let mut start = None;
+    for (i, c) in format.char_indices() {
+        match (c, start) {
+            ('%', _) if format[i + 1..].starts_with('[') && !format[..i].ends_with('\\') => {
+                if start.is_some() {
+                    return Err(Error::new(format!(
+                        "nested conditional segment at index {i}"
+                    )));
+                }
+                start = Some(i);
             }
+            (']', Some(s)) if i > s + 1 => {
+                groups.push(Group(s, i + 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        return Err(Error::new(format!(
+            "unterminated conditional segment at index {s}"
+        )));
+    }
+    Ok(groups)
+}
+
+/// Parse a long-form markup, e.g. `{battery}` or `{battery:bat1}`,
+/// starting at the given index of the opening brace
+fn parse_named_markup(
+    format: &str,
+    start: usize,
+    registry: &Registry,
+) -> Result<MarkupMatch, Error> {
+    let close = format[start..]
+        .find('}')
+        .map(|i| start + i)
+        .ok_or_else(|| Error::new(format!("unterminated markup at index {}", start - 1)))?;
+    let content = &format[start + 1..close];
+    let (name, instance) = match content.split_once(':') {
+        Some((_, "")) => {
+            return Err(Error::new(format!(
+                "empty instance id in markup \"%{{{content}}}\""
+            )));
         }
+        Some((name, id)) => (name, Some(id.to_string())),
+        None => (content, None),
+    };
+    if !registry.names().any(|n| n == name) {
+        return Err(Error::new(format!(
+            "unknown module \"{name}\" in markup \"%{{{content}}}\""
+        )));
     }
-    matches
+    Ok(MarkupMatch(name.to_string(), start, instance, close + 1))
+}
+
+/// Parse the instance id following a markup key, e.g. `{bat1}` in `%a{bat1}`.
+/// Returns the id and the length of the braces block
+fn parse_instance(s: &str) -> Option<(String, usize)> {
+    let id = s.strip_prefix('{')?;
+    let end = id.find('}')?;
+    let id = &id[..end];
+    if id.is_empty() || id.contains(['%', '{']) {
+        return None;
+    }
+    Some((id.to_string(), end + 2))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use modules::{battery, sound, wired};
+
+    fn parse_format(format: &str) -> Result<Vec<MarkupMatch>, Error> {
+        super::parse_format(format, &Registry::default())
+    }
 
     #[test]
     fn parse_empty_format() {
-        let result = parse_format("");
+        let result = parse_format("").unwrap();
         assert!(result.is_empty());
     }
 
     #[test]
     fn parse_one_char() {
-        let result = parse_format("a");
+        let result = parse_format("a").unwrap();
         assert!(result.is_empty());
     }
 
     #[test]
     fn parse_one_percent() {
-        let result = parse_format("%");
+        let result = parse_format("%").unwrap();
         assert!(result.is_empty());
     }
 
     #[test]
     fn parse_one_escaped_percent_i() {
-        let result = parse_format("\\%");
+        let result = parse_format("\\%").unwrap();
         assert!(result.is_empty());
     }
 
     #[test]
     fn parse_one_escaped_percent_ii() {
-        let result = parse_format("\\%%");
+        let result = parse_format("\\%%").unwrap();
         assert!(result.is_empty());
     }
 
     #[test]
     fn parse_one_escaped_and_one_markup() {
-        let result = parse_format("\\%%a");
+        let result = parse_format("\\%%a").unwrap();
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0].0, 'a');
+        assert_eq!(result[0].0, "battery");
         assert_eq!(result[0].1, 3);
     }
 
     #[test]
     fn parse_peaceful_markup() {
-        let result = parse_format("%a");
+        let result = parse_format("%a").unwrap();
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0].0, 'a');
+        assert_eq!(result[0].0, "battery");
         assert_eq!(result[0].1, 1);
     }
 
     #[test]
     fn parse_easy_markup() {
-        let result = parse_format("\\%a%b");
+        let result = parse_format("\\%a%b").unwrap();
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0].0, 'b');
+        assert_eq!(result[0].0, "brightness");
         assert_eq!(result[0].1, 4);
     }
 
     #[test]
     fn parse_normal_markup() {
-        let result = parse_format("\\%a%b%c");
+        let result = parse_format("\\%a%b%c").unwrap();
         assert_eq!(result.len(), 2);
-        assert_eq!(result[0].0, 'b');
+        assert_eq!(result[0].0, "brightness");
         assert_eq!(result[0].1, 4);
-        assert_eq!(result[1].0, 'c');
+        assert_eq!(result[1].0, "cpu_usage");
         assert_eq!(result[1].1, 6);
     }
 
     #[test]
     fn parse_hard_markup() {
-        let result = parse_format("\\%a%b%c \\% %a\\% %");
+        let result = parse_format("\\%a%b%c \\% %a\\% %").unwrap();
         assert_eq!(result.len(), 3);
-        assert_eq!(result[0].0, 'b');
+        assert_eq!(result[0].0, "brightness");
         assert_eq!(result[0].1, 4);
-        assert_eq!(result[1].0, 'c');
+        assert_eq!(result[1].0, "cpu_usage");
         assert_eq!(result[1].1, 6);
-        assert_eq!(result[2].0, 'a');
+        assert_eq!(result[2].0, "battery");
         assert_eq!(result[2].1, 12);
     }
+
+    #[test]
+    fn parse_instance_markup() {
+        let result = parse_format("%a{bat0} %a{bat1}%b").unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].0, "battery");
+        assert_eq!(result[0].1, 1);
+        assert_eq!(result[0].2.as_deref(), Some("bat0"));
+        assert_eq!(result[0].3, 8);
+        assert_eq!(result[1].0, "battery");
+        assert_eq!(result[1].1, 10);
+        assert_eq!(result[1].2.as_deref(), Some("bat1"));
+        assert_eq!(result[1].3, 17);
+        assert_eq!(result[2].0, "brightness");
+        assert_eq!(result[2].2, None);
+        assert_eq!(result[2].3, 19);
+    }
+
+    #[test]
+    fn parse_invalid_instance_markup() {
+        let result = parse_format("%a{} %b{bat1").unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, "battery");
+        assert_eq!(result[0].2, None);
+        assert_eq!(result[0].3, 2);
+        assert_eq!(result[1].0, "brightness");
+        assert_eq!(result[1].2, None);
+        assert_eq!(result[1].3, 7);
+    }
+
+    #[test]
+    fn parse_named_markup() {
+        let result = parse_format("%{battery} %{cpu_usage}\\%{x}%{battery:bat1}%a").unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].0, "battery");
+        assert_eq!(result[0].1, 1);
+        assert_eq!(result[0].2, None);
+        assert_eq!(result[0].3, 10);
+        assert_eq!(result[1].0, "cpu_usage");
+        assert_eq!(result[1].1, 12);
+        assert_eq!(result[1].3, 23);
+        assert_eq!(result[2].0, "battery");
+        assert_eq!(result[2].1, 29);
+        assert_eq!(result[2].2.as_deref(), Some("bat1"));
+        assert_eq!(result[2].3, 43);
+        assert_eq!(result[3].0, "battery");
+        assert_eq!(result[3].1, 44);
+    }
+
+    #[test]
+    fn parse_unknown_named_markup() {
+        let result = parse_format("%a %{batery}");
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("unknown module \"batery\"")
+        );
+        assert!(parse_format("%{}").is_err());
+    }
+
+    #[test]
+    fn parse_unterminated_named_markup() {
+        let result = parse_format("%a %{battery");
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("unterminated markup at index 3")
+        );
+        assert!(parse_format("%{battery:}").is_err());
+    }
+
+    #[test]
+    fn parse_group_markup() {
+        let result = parse_format("%a%[ | %b]").unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, "battery");
+        assert_eq!(result[1].0, "brightness");
+        assert_eq!(result[1].1, 8);
+    }
+
+    #[test]
+    fn parse_groups_format() {
+        assert!(parse_groups("%a %b").unwrap().is_empty());
+        assert!(parse_groups("\\%[%a]").unwrap().is_empty());
+        assert_eq!(parse_groups("é%[%a]").unwrap(), vec![Group(2, 7)]);
+        let result = parse_groups("%a%[ | %b]%[ %c]").unwrap();
+        assert_eq!(result, vec![Group(2, 10), Group(10, 16)]);
+        assert!(result[0].contains(&parse_format("%a%[ | %b]").unwrap()[1]));
+    }
+
+    #[test]
+    fn parse_invalid_groups() {
+        let result = parse_groups("%[ %a");
+        assert!(result.unwrap_err().to_string().contains("unterminated"));
+        let result = parse_groups("%[ %[%a]]");
+        assert!(result.unwrap_err().to_string().contains("nested"));
+    }
+
+    #[test]
+    fn deserialize_instances() {
+        let config: Config = serde_yaml::from_str(
+            "format: '%a{bat1}'\nbattery:\n  - id: bat0\n  - id: bat1\n    name: BAT1\nwired:\n  discrete: true\n",
+        )
+        .unwrap();
+        let battery = config.instances::<battery::Config>("battery").unwrap();
+        assert!(battery.get(None).unwrap().is_some());
+        assert!(battery.get(Some("bat1")).unwrap().is_some());
+        assert!(battery.get(Some("bat2")).is_err());
+        let wired = config.instances::<wired::Config>("wired").unwrap();
+        assert!(wired.first().is_some());
+        let sound = config.instances::<sound::Config>("sound").unwrap();
+        assert!(sound.first().is_none());
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    struct HelloConfig {
+        text: String,
+        /// Send a percentage in place of the text
+        percent: Option<f64>,
+        #[serde(flatten)]
+        common: CommonConfig,
+    }
+
+    impl Instance for HelloConfig {
+        fn common(&self) -> &CommonConfig {
+            &self.common
+        }
+    }
+
+    struct Hello;
+
+    impl Module for Hello {
+        type Config = HelloConfig;
+
+        fn name(&self) -> &str {
+            "hello"
+        }
+
+        fn run(
+            &self,
+            _control: &Control,
+            config: Option<&HelloConfig>,
+            tx: &ModuleSender,
+        ) -> Result<(), Error> {
+            let text = config.map_or("hello", |c| &c.text);
+            let value = match config.and_then(|c| c.percent) {
+                Some(p) => Value::percent(p),
+                None => Value::Text(text.to_string()),
+            };
+            tx.send(Data::new(Some(value), None, State::Normal))
+        }
+    }
+
+    #[test]
+    fn register_modules() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        assert!(registry.register(Hello).is_err());
+        assert!(registry.register(battery::Battery).is_err());
+        let result = super::parse_format("%a %{hello}", &registry).unwrap();
+        assert_eq!(result[1].0, "hello");
+        assert!(parse_format("%{hello}").is_err());
+    }
+
+    #[test]
+    fn run_registered_module() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config =
+            serde_yaml::from_str("format: '[%{hello}]'\nhello:\n  text: world\n  format: '%v!'\n")
+                .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        assert_eq!(baru.render().unwrap(), "[world!]");
+        baru.cleanup();
+    }
+
+    #[test]
+    fn carry_data_on_reload() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config = serde_yaml::from_str(
+            "format: '%{hello:a} %{hello:b}'
+hello:
+  - id: a
+    text: a
+  - id: b
+    text: b
+",
+        )
+        .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        let session = baru.stop();
+        // not started, only the data carried from the previous instance
+        let baru = Baru::with_registry(&config, &registry, session).unwrap();
+        assert_eq!(baru.render().unwrap(), "a b");
+        let session = baru.stop();
+        let changed: Config = serde_yaml::from_str(
+            "format: '%{hello:a} %{hello:b}'
+hello:
+  - id: a
+    text: a
+  - id: b
+    text: c
+",
+        )
+        .unwrap();
+        let baru = Baru::with_registry(&changed, &registry, session).unwrap();
+        assert_eq!(baru.render().unwrap(), "- -");
+    }
+
+    #[test]
+    fn render_groups() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config = serde_yaml::from_str(
+            "format: 'é%[ | %{hello:a}]%[ | %{hello:b}]%[ é]'
+hello:
+  - id: a
+    text: ''
+  - id: b
+    text: b
+",
+        )
+        .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        assert_eq!(baru.render().unwrap(), "é | b é");
+        baru.cleanup();
+    }
+
+    #[test]
+    fn render_colors() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config = serde_yaml::from_str(
+            "format: '%{hello:a} %{hello:b}'
+output: xmobar
+hello:
+  - id: a
+    text: a
+    color: '#0000ff'
+    colors:
+      normal: '#00ff00'
+  - id: b
+    text: b
+    color: '#0000ff'
+    colors:
+      critical: '#ff0000'
+",
+        )
+        .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        assert_eq!(
+            baru.render().unwrap(),
+            "<fc=#00ff00>a</fc> <fc=#0000ff>b</fc>"
+        );
+        baru.cleanup();
+        let config: Config =
+            serde_yaml::from_str("format: '%{hello}'\nhello:\n  text: a\n  color: red\n").unwrap();
+        assert!(Baru::with_registry(&config, &registry, Session::default()).is_err());
+    }
+
+    #[test]
+    fn render_escaped() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config = serde_yaml::from_str(
+            "format: 'x %{hello}'
+output: lemonbar
+hello:
+  text: '50%'
+  format: '%v%%'
+  on_click:
+    command: 'echo a:b'
+",
+        )
+        .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        assert_eq!(baru.render().unwrap(), "x %{A1:echo a\\:b:}50%%%%%{A}");
+        baru.cleanup();
+    }
+
+    #[test]
+    fn render_levels() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config = serde_yaml::from_str(
+            "format: '%{hello:a} %{hello:b} %{hello:c}'
+output: xmobar
+hello:
+  - id: a
+    text: a
+    percent: 60
+    format: '%l%v'
+    levels: &levels
+      - above: 50
+        label: hi
+        color: '#ff0000'
+        state: critical
+      - above: 10
+        label: lo
+  - id: b
+    text: b
+    percent: 30
+    format: '%l%v'
+    levels: *levels
+  - id: c
+    text: c
+    percent: 5
+    format: '%l%v'
+    levels: *levels
+",
+        )
+        .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        assert_eq!(
+            baru.render().unwrap(),
+            "<fc=#ff0000>hi 60%</fc> lo 30%   5%"
+        );
+        assert!(baru.modules[0].block().urgent);
+        baru.cleanup();
+    }
+
+    #[test]
+    fn render_ramps() {
+        let mut registry = Registry::default();
+        registry.register(Hello).unwrap();
+        let config: Config = serde_yaml::from_str(
+            "format: '%{hello:a} %{hello:b} %{hello:c} %{hello:d}'
+hello:
+  - id: a
+    text: a
+    percent: 0
+    format: '%r%v'
+    ramp: &ramp ['1', '2', '3', '4', '5']
+  - id: b
+    text: b
+    percent: 60
+    format: '%r%v'
+    ramp: *ramp
+  - id: c
+    text: c
+    percent: 100
+    format: '%r%v'
+    ramp: *ramp
+    ramps:
+      critical: ['!']
+    levels:
+      - above: 90
+        state: critical
+  - id: d
+    text: d
+    format: '[%r]%v'
+    ramp: *ramp
+",
+        )
+        .unwrap();
+        let mut baru = Baru::with_registry(&config, &registry, Session::default()).unwrap();
+        baru.start().unwrap();
+        baru.wait_ready(Duration::from_secs(2));
+        assert_eq!(baru.render().unwrap(), "1  0% 4 60% !100% []d");
+        baru.cleanup();
+        let config: Config =
+            serde_yaml::from_str("format: '%{hello}'\nhello:\n  text: a\n  ramp: []\n").unwrap();
+        assert!(Baru::with_registry(&config, &registry, Session::default()).is_err());
+    }
 }
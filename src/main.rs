@@ -3,25 +3,34 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use anyhow::{Context, Result};
-use baru::cli::Cli;
-use baru::{Baru, Config, RUN, signal, trace, util};
+use baru::check;
+use baru::cli::{Cli, Command};
+use baru::ipc::{self, Reply};
+use baru::protocol::Protocol;
+use baru::{Baru, Config, RELOAD, RUN, Registry, Session, plugin, signal, trace, util};
 use clap::Parser;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::exit;
 use std::sync::atomic::Ordering;
-use std::thread;
-use std::time::{Duration, Instant};
-use tracing::{debug, error, info};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error, info, warn};
 
 const XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
 const APP_DIR: &str = "baru";
 const CONFIG_FILE: &str = "baru.yaml";
-const TICK_RATE: Duration = Duration::from_millis(50);
+const PLUGINS_DIR: &str = "plugins";
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let _g = trace::init(cli.logs).context("failed to init tracing")?;
+    let _g = trace::init(cli.logs.clone()).context("failed to init tracing")?;
+
+    let socket = cli.socket.clone().or_else(ipc::socket_path);
+    if let Some(Command::Msg { command }) = &cli.command {
+        let socket = socket.context("no IPC socket, XDG_RUNTIME_DIR is not set")?;
+        return send_message(&socket, &command.join(" "));
+    }
 
     signal::catch_signals()?;
 
@@ -34,17 +43,90 @@ fn main() -> Result<()> {
 
     let config_file = config_dir.join(CONFIG_FILE);
     info!("config file: {:?}", config_file);
+    let mut registry = Registry::default();
+    plugin::load_dir(&config_dir.join(PLUGINS_DIR), &mut registry);
+    if let Some(Command::Check) = cli.command {
+        return check_config(&config_file, &registry);
+    }
+    let mut config = load_config(&cli, &config_file)?;
+    if cli.once {
+        return run_once(&cli, &mut config, &registry);
+    }
+    let mut session = Session::default();
+    match &socket {
+        Some(path) => {
+            if let Err(e) = session.listen(path) {
+                warn!("IPC disabled: {}", e);
+            }
+        }
+        None => warn!("IPC disabled: XDG_RUNTIME_DIR is not set"),
+    }
+    loop {
+        let (next, s) = run(&cli, &config_file, &config, &registry, session)?;
+        session = s;
+        match next {
+            Some(c) => config = c,
+            None => break,
+        }
+    }
+    info!("exiting");
+    Ok(())
+}
+
+fn check_config(config_file: &Path, registry: &Registry) -> Result<()> {
+    let content = fs::read_to_string(config_file)
+        .with_context(|| format!("failed to read config file {config_file:?}"))?;
+    let problems = check::check(&content, registry);
+    if problems.is_empty() {
+        println!("{}: ok", config_file.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}: {}", config_file.display(), problem);
+    }
+    eprintln!("{} problem(s) found", problems.len());
+    exit(1);
+}
+
+fn send_message(socket: &Path, command: &str) -> Result<()> {
+    match ipc::send(socket, command)? {
+        Reply::Ok(serde_json::Value::Null) => Ok(()),
+        Reply::Ok(value) => {
+            println!("{}", serde_json::to_string_pretty(&value)?);
+            Ok(())
+        }
+        Reply::Error(e) => {
+            eprintln!("error: {e}");
+            exit(1);
+        }
+    }
+}
+
+fn load_config(cli: &Cli, config_file: &Path) -> Result<Config> {
     let content = fs::read_to_string(config_file)
         .inspect_err(|e| error!("failed to read config file: {}", e))?;
-    let config: Config = serde_yaml::from_str(&content)
+    let mut config: Config = serde_yaml::from_str(&content)
         .inspect_err(|e| error!("failed to parse config file: {}", e))?;
+    if let Some(name) = &cli.module {
+        config.select_module(name)?;
+    }
+    if cli.waybar {
+        config.set_protocol(Protocol::Waybar);
+    }
     debug!("{:#?}", config);
+    Ok(config)
+}
 
-    let tick = match config.tick {
-        Some(ms) => Duration::from_millis(ms as u64),
-        None => TICK_RATE,
-    };
-    let mut baru = Baru::with_config(&config)
+/// Run baru with the given config until exit or until a new config is loaded.
+/// Returns the new config, if any, and the output session
+fn run(
+    cli: &Cli,
+    config_file: &Path,
+    config: &Config,
+    registry: &Registry,
+    session: Session,
+) -> Result<(Option<Config>, Session)> {
+    let mut baru = Baru::with_registry(config, registry, session)
         .inspect_err(|e| error!("failed to create baru instance {}", e))?;
     info!("baru instance initialized");
 
@@ -55,21 +137,64 @@ fn main() -> Result<()> {
     baru.start()
         .inspect_err(|e| error!("failed to start {}", e))?;
     info!("started");
-    let mut iteration_start: Instant;
-    let mut iteration_end: Duration;
 
+    let mut modified = modified_time(config_file);
     info!("launching main loop");
     while RUN.load(Ordering::Relaxed) {
-        iteration_start = Instant::now();
         baru.update()
             .inspect_err(|e| error!("failed to update: {}", e))?;
-        iteration_end = iteration_start.elapsed();
-        if iteration_end < tick {
-            thread::sleep(tick - iteration_end);
+        let mut reload = RELOAD.swap(false, Ordering::Relaxed);
+        if config.watch_config() {
+            let time = modified_time(config_file);
+            if time != modified {
+                info!("config file changed, reloading config");
+                modified = time;
+                reload = true;
+            }
+        }
+        if !reload {
+            continue;
+        }
+        // on error, keep running with the current config
+        match load_config(cli, config_file) {
+            Ok(new_config) => {
+                if let Err(e) = Baru::with_registry(&new_config, registry, Session::default()) {
+                    error!("invalid config, not reloaded: {}", e);
+                    continue;
+                }
+                config.warn_restart(&new_config);
+                info!("config reloaded");
+                return Ok((Some(new_config), baru.stop()));
+            }
+            Err(e) => error!("failed to reload config: {}", e),
         }
     }
 
     baru.cleanup();
-    info!("exiting");
+    Ok((None, baru.stop()))
+}
+
+/// Print a single output once every module has data, or on timeout
+fn run_once(cli: &Cli, config: &mut Config, registry: &Registry) -> Result<()> {
+    if config.protocol() == Protocol::I3bar {
+        // no endless array, print the plain output
+        config.set_protocol(Protocol::Plain);
+    }
+    let mut baru = Baru::with_registry(config, registry, Session::default())
+        .inspect_err(|e| error!("failed to create baru instance {}", e))?;
+    baru.start()
+        .inspect_err(|e| error!("failed to start {}", e))?;
+    baru.wait_ready(Duration::from_millis(cli.timeout));
+    let output = match cli.json {
+        true => baru.render_json()?,
+        false => baru.render()?,
+    };
+    println!("{}", output);
+    RUN.store(false, Ordering::Relaxed);
+    baru.cleanup();
     Ok(())
 }
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
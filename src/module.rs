@@ -2,145 +2,625 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::Config;
-use crate::ModuleMsg;
+use crate::action::{self, Action, Builtin};
+use crate::click::Button;
+use crate::control::Control;
 use crate::error::Error;
-use crate::modules::battery::Battery;
-use crate::modules::brightness::Brightness;
-use crate::modules::cpu_freq::CpuFreq;
-use crate::modules::cpu_usage::CpuUsage;
-use crate::modules::date_time::DateTime;
-use crate::modules::memory::Memory;
-use crate::modules::mic::Mic;
-use crate::modules::sound::Sound;
-use crate::modules::temperature::Temperature;
-use crate::modules::weather::Weather;
-use crate::modules::wired::Wired;
-use crate::modules::wireless::Wireless;
+use crate::modules::{
+    battery, brightness, command, cpu_freq, cpu_usage, date_time, memory, mic, script, sound,
+    stream, temperature, weather, wired, wireless,
+};
+use crate::output::{Backend, Color, Output};
+use crate::protocol::{Block, ModuleStatus, Snapshot, WaybarOutput};
+use crate::pulse::{self, Target};
+use crate::signal;
+use crate::value::{self, Data, State, Value};
+use crate::{Config, ModuleMsg, ROOT_OPTIONS};
 
 use anyhow::{Result, anyhow};
-use std::convert::TryFrom;
-use std::sync::atomic::AtomicBool;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::Value as YamlValue;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::sync::Arc;
 use std::sync::mpsc::Sender;
 use std::thread::JoinHandle;
-use tracing::{error, info, instrument};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, instrument, warn};
 
 const MODULE_FAILED_ICON: &str = "✗";
+const RESTART_MAX: u32 = 5;
+const RESTART_BACKOFF: u32 = 1000;
+const RESTART_MAX_BACKOFF: u32 = 60000;
+const PLACEHOLDER: &str = "-";
+const FORMAT: &str = "%v";
 
-pub type RunPtr = fn(&AtomicBool, char, Config, Sender<ModuleMsg>) -> Result<(), Error>;
+/// A module of the bar, built-in or registered by a third-party crate,
+/// see [`Registry`].
+///
+/// The config of the module is read from the section named after the module,
+/// it is declared either as a single config or as a list of instances.
+/// Each instance runs in its own thread, until the module is stopped.
+pub trait Module: Send + Sync + 'static {
+    type Config: DeserializeOwned + Serialize + Instance + Clone + Debug + Send + 'static;
 
-pub trait Bar {
+    /// Name of the module, used by the markup `%{name}` and as config section
+    fn name(&self) -> &str;
+
+    /// Default format of the module, see the value specifiers of `%v`
+    fn format(&self) -> &str {
+        FORMAT
+    }
+
+    /// Default text displayed until the module sends its first data
+    fn placeholder(&self) -> &str {
+        PLACEHOLDER
+    }
+
+    /// Default levels of the module, used when the instance declares no
+    /// `levels`, e.g. from its `high_level` and `high_label` options
+    fn levels(&self, _config: Option<&Self::Config>) -> Vec<Level> {
+        vec![]
+    }
+
+    /// Check the config of an instance, as done when the module starts.
+    /// `None` if the module is used without being declared
+    fn check(&self, _config: Option<&Self::Config>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Run the module until stopped, sending its data on each update.
+    /// The module sleeps on `control` between two updates so it can be
+    /// woken up, paused or stopped at any time
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Self::Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error>;
+}
+
+/// Sender of the data of a module instance
+#[derive(Debug, Clone)]
+pub struct ModuleSender {
+    index: usize,
+    tx: Sender<ModuleMsg>,
+}
+
+impl ModuleSender {
+    pub(crate) fn new(index: usize, tx: Sender<ModuleMsg>) -> Self {
+        ModuleSender { index, tx }
+    }
+
+    /// Send the data of the module, it fails once the bar has stopped
+    pub fn send(&self, data: Data) -> Result<(), Error> {
+        self.tx.send(ModuleMsg(self.index, data))?;
+        Ok(())
+    }
+}
+
+/// A module config, with the options shared by all modules flattened into it.
+/// The config can be declared several times, identified by an id
+pub trait Instance {
+    fn common(&self) -> &CommonConfig;
+
+    fn id(&self) -> Option<&str> {
+        self.common().id.as_deref()
+    }
+}
+
+/// Instances of a module config.
+/// Declared either as a single config or as a list of configs
+#[derive(Debug, Clone)]
+pub struct Instances<T>(Vec<T>);
+
+impl<T> Default for Instances<T> {
+    fn default() -> Self {
+        Instances(vec![])
+    }
+}
+
+impl<T: Instance> Instances<T> {
+    /// The default instance, i.e. the first one
+    pub fn first(&self) -> Option<&T> {
+        self.0.first()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter()
+    }
+
+    /// Get the config of an instance from its id, or the default instance
+    /// if no id is given
+    pub fn get(&self, id: Option<&str>) -> Result<Option<&T>, Error> {
+        let Some(id) = id else {
+            return Ok(self.first());
+        };
+        self.0
+            .iter()
+            .find(|c| c.id() == Some(id))
+            .map(Some)
+            .ok_or_else(|| Error::new(format!("instance \"{id}\" not found")))
+    }
+}
+
+impl<T: DeserializeOwned> Instances<T> {
+    /// Deserialize the instances from the config section of a module
+    pub fn from_section(section: Option<&YamlValue>) -> Result<Self, Error> {
+        match section {
+            Some(s) => Ok(Instances::deserialize(s)?),
+            None => Ok(Instances::default()),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Instances<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct InstancesVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for InstancesVisitor<T> {
+            type Value = Instances<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a module config or a list of module configs")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(Instances(vec![]))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                Ok(Instances(vec![T::deserialize(
+                    MapAccessDeserializer::new(map),
+                )?]))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Ok(Instances(Vec::deserialize(SeqAccessDeserializer::new(
+                    seq,
+                ))?))
+            }
+        }
+
+        deserializer.deserialize_any(InstancesVisitor(PhantomData))
+    }
+}
+
+impl<T: Serialize> Serialize for Instances<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0.as_slice() {
+            [config] => config.serialize(serializer),
+            configs => configs.serialize(serializer),
+        }
+    }
+}
+
+/// Options shared by all modules, flattened into each module config
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CommonConfig {
+    /// Instance id, to declare the same module several times
+    pub id: Option<String>,
+    /// Format of the module, replacing its default format
+    pub format: Option<String>,
+    /// Displayed until the module sends its first data
+    pub placeholder: Option<String>,
+    /// Color of the module, see the root option `output`
+    pub color: Option<String>,
+    /// Color of the module for each state of its value, replacing `color`
+    pub colors: Option<StatesConfig<String>>,
+    /// Icons picked by the percentage of the module, `%r`
+    pub ramp: Option<Vec<String>>,
+    /// Ramp of the module for each state of its value, replacing `ramp`
+    pub ramps: Option<StatesConfig<Vec<String>>>,
+    /// Levels of a numeric module, replacing its default levels
+    pub levels: Option<Vec<Level>>,
+    /// i3bar protocol, whether a separator is drawn after the module block
+    pub separator: Option<bool>,
+    /// Alternative format, see the built-in action `toggle_format`
+    pub alt_format: Option<String>,
+    /// i3bar protocol, action triggered by a left click on the module block
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub on_click: Option<Action>,
+    /// i3bar protocol, action triggered by a scroll up on the module block
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub on_scroll_up: Option<Action>,
+    /// i3bar protocol, action triggered by a scroll down on the module block
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub on_scroll_down: Option<Action>,
+    /// Restart policy of the module when it fails
+    pub restart: Option<RestartConfig>,
+    /// Update the module on the real-time signal `SIGRTMIN+signal`
+    pub signal: Option<u32>,
+}
+
+/// Option of a module by state, e.g. the colors `high: '#ff0000'`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StatesConfig<T> {
+    pub normal: Option<T>,
+    pub high: Option<T>,
+    pub low: Option<T>,
+    pub charging: Option<T>,
+    pub full: Option<T>,
+    pub unknown: Option<T>,
+    pub muted: Option<T>,
+    pub disconnected: Option<T>,
+    pub warning: Option<T>,
+    pub critical: Option<T>,
+}
+
+impl<T> StatesConfig<T> {
+    /// The declared options and their state
+    fn iter(&self) -> impl Iterator<Item = (State, &T)> {
+        [
+            (State::Normal, &self.normal),
+            (State::High, &self.high),
+            (State::Low, &self.low),
+            (State::Charging, &self.charging),
+            (State::Full, &self.full),
+            (State::Unknown, &self.unknown),
+            (State::Muted, &self.muted),
+            (State::Disconnected, &self.disconnected),
+            (State::Warning, &self.warning),
+            (State::Critical, &self.critical),
+        ]
+        .into_iter()
+        .filter_map(|(state, option)| Some((state, option.as_ref()?)))
+    }
+}
+
+/// An option and its declarations by state, `None` being the default one
+fn by_state<'a, T>(
+    default: Option<&'a T>,
+    states: Option<&'a StatesConfig<T>>,
+) -> impl Iterator<Item = (Option<State>, &'a T)> {
+    let states = states.into_iter().flat_map(|s| s.iter());
+    default
+        .map(|d| (None, d))
+        .into_iter()
+        .chain(states.map(|(state, option)| (Some(state), option)))
+}
+
+/// The option of the given state, or the default one
+fn state_option<T>(options: &[(Option<State>, T)], state: State) -> Option<&T> {
+    options
+        .iter()
+        .find(|(s, _)| *s == Some(state))
+        .or_else(|| options.iter().find(|(s, _)| s.is_none()))
+        .map(|(_, option)| option)
+}
+
+/// Threshold of a numeric module, reached once the percentage of the
+/// module value is at least `above`, or exceeds it when `strict`. The value
+/// itself is compared when it has no maximum, e.g. a temperature
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Level {
+    pub above: f64,
+    /// The value must be greater than `above`
+    #[serde(default)]
+    pub strict: bool,
+    /// Replaces the label of the module
+    pub label: Option<String>,
+    /// Replaces the color of the module, see `color` and `colors`
+    pub color: Option<String>,
+    /// State of the module value, e.g. `critical` for an urgent i3bar block
+    pub state: Option<State>,
+}
+
+impl Level {
+    pub fn new(above: f64, label: &str, state: State) -> Self {
+        Level {
+            above,
+            label: Some(label.to_string()),
+            color: None,
+            state: Some(state),
+            strict: false,
+        }
+    }
+
+    /// Only reached once the value is greater than `above`
+    pub fn strict(self) -> Self {
+        Level {
+            strict: true,
+            ..self
+        }
+    }
+
+    fn reached(&self, value: f64) -> bool {
+        match self.strict {
+            true => value > self.above,
+            false => value >= self.above,
+        }
+    }
+}
+
+/// Restart policy of a failed module
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RestartConfig {
+    /// Consecutive restarts before giving up, 0 disables the restarts
+    pub max: Option<u32>,
+    /// Delay before the first restart in milliseconds, doubled at each restart
+    pub backoff: Option<u32>,
+    /// Maximum delay between two restarts in milliseconds
+    pub max_backoff: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RestartPolicy {
+    max: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl From<Option<&RestartConfig>> for RestartPolicy {
+    fn from(config: Option<&RestartConfig>) -> Self {
+        RestartPolicy {
+            max: config.and_then(|c| c.max).unwrap_or(RESTART_MAX),
+            backoff: Duration::from_millis(
+                config.and_then(|c| c.backoff).unwrap_or(RESTART_BACKOFF) as u64,
+            ),
+            max_backoff: Duration::from_millis(
+                config
+                    .and_then(|c| c.max_backoff)
+                    .unwrap_or(RESTART_MAX_BACKOFF) as u64,
+            ),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Delay before the given restart, starting at 0
+    fn delay(&self, restart: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(restart))
+            .min(self.max_backoff)
+    }
+}
+
+impl CommonConfig {
+    pub fn action(&self, button: Button) -> Option<&Action> {
+        match button {
+            Button::Left => self.on_click.as_ref(),
+            Button::ScrollUp => self.on_scroll_up.as_ref(),
+            Button::ScrollDown => self.on_scroll_down.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Function run by the thread of a module instance
+type Runner = Box<dyn FnOnce(&Control, &ModuleSender) -> Result<(), Error> + Send>;
+
+/// A module bound to the config of one of its instances
+pub(crate) trait Bar: Debug {
     fn name(&self) -> &str;
-    fn run_fn(&self) -> RunPtr;
     fn placeholder(&self) -> &str;
     fn format(&self) -> &str;
+    fn common(&self) -> Option<&CommonConfig>;
+    /// The levels of the instance, or the default levels of the module
+    fn levels(&self) -> Vec<Level>;
+    /// Build the function run by the module thread
+    fn runner(&self) -> Runner;
 }
 
-#[derive(Debug)]
-pub enum Module<'a> {
-    Battery(Battery<'a>),
-    Brightness(Brightness<'a>),
-    CpuUsage(CpuUsage<'a>),
-    CpuFreq(CpuFreq<'a>),
-    DateTime(DateTime<'a>),
-    Memory(Memory<'a>),
-    Mic(Mic<'a>),
-    Wired(Wired<'a>),
-    Sound(Sound<'a>),
-    Temperature(Temperature<'a>),
-    Wireless(Wireless<'a>),
-    Weather(Weather<'a>),
-}
-
-impl<'a> TryFrom<(char, &'a Config)> for Module<'a> {
-    type Error = Error;
-
-    fn try_from((key, config): (char, &'a Config)) -> Result<Self, Self::Error> {
-        match key {
-            'a' => Ok(Module::Battery(Battery::with_config(config))),
-            'b' => Ok(Module::Brightness(Brightness::with_config(config))),
-            'c' => Ok(Module::CpuUsage(CpuUsage::with_config(config))),
-            'd' => Ok(Module::DateTime(DateTime::with_config(config))),
-            'e' => Ok(Module::Wired(Wired::with_config(config))),
-            'f' => Ok(Module::CpuFreq(CpuFreq::with_config(config))),
-            'i' => Ok(Module::Mic(Mic::with_config(config))),
-            'm' => Ok(Module::Memory(Memory::with_config(config))),
-            'r' => Ok(Module::Weather(Weather::with_config(config))),
-            's' => Ok(Module::Sound(Sound::with_config(config))),
-            't' => Ok(Module::Temperature(Temperature::with_config(config))),
-            'w' => Ok(Module::Wireless(Wireless::with_config(config))),
-            _ => Err(Error::new(format!("unknown markup \"{key}\""))),
-        }
-    }
-}
-
-impl<'a> Bar for Module<'a> {
+struct Bound<M: Module> {
+    module: Arc<M>,
+    config: Option<M::Config>,
+}
+
+impl<M: Module> Debug for Bound<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Bound")
+            .field("name", &self.module.name())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<M: Module> Bar for Bound<M> {
     fn name(&self) -> &str {
-        match self {
-            Module::Battery(m) => m.name(),
-            Module::Brightness(m) => m.name(),
-            Module::CpuUsage(m) => m.name(),
-            Module::CpuFreq(m) => m.name(),
-            Module::DateTime(m) => m.name(),
-            Module::Memory(m) => m.name(),
-            Module::Mic(m) => m.name(),
-            Module::Wired(m) => m.name(),
-            Module::Sound(m) => m.name(),
-            Module::Temperature(m) => m.name(),
-            Module::Weather(m) => m.name(),
-            Module::Wireless(m) => m.name(),
-        }
-    }
-
-    fn run_fn(&self) -> RunPtr {
-        match self {
-            Module::Battery(m) => m.run_fn(),
-            Module::Brightness(m) => m.run_fn(),
-            Module::CpuUsage(m) => m.run_fn(),
-            Module::CpuFreq(m) => m.run_fn(),
-            Module::DateTime(m) => m.run_fn(),
-            Module::Memory(m) => m.run_fn(),
-            Module::Mic(m) => m.run_fn(),
-            Module::Sound(m) => m.run_fn(),
-            Module::Temperature(m) => m.run_fn(),
-            Module::Weather(m) => m.run_fn(),
-            Module::Wired(m) => m.run_fn(),
-            Module::Wireless(m) => m.run_fn(),
-        }
+        self.module.name()
     }
 
     fn placeholder(&self) -> &str {
-        match self {
-            Module::Battery(m) => m.placeholder(),
-            Module::Brightness(m) => m.placeholder(),
-            Module::CpuUsage(m) => m.placeholder(),
-            Module::CpuFreq(m) => m.placeholder(),
-            Module::DateTime(m) => m.placeholder(),
-            Module::Memory(m) => m.placeholder(),
-            Module::Wired(m) => m.placeholder(),
-            Module::Mic(m) => m.placeholder(),
-            Module::Sound(m) => m.placeholder(),
-            Module::Temperature(m) => m.placeholder(),
-            Module::Weather(m) => m.placeholder(),
-            Module::Wireless(m) => m.placeholder(),
-        }
+        self.common()
+            .and_then(|c| c.placeholder.as_deref())
+            .unwrap_or_else(|| self.module.placeholder())
     }
 
     fn format(&self) -> &str {
-        match self {
-            Module::Battery(m) => m.format(),
-            Module::Brightness(m) => m.format(),
-            Module::CpuUsage(m) => m.format(),
-            Module::CpuFreq(m) => m.format(),
-            Module::DateTime(m) => m.format(),
-            Module::Memory(m) => m.format(),
-            Module::Wired(m) => m.format(),
-            Module::Mic(m) => m.format(),
-            Module::Sound(m) => m.format(),
-            Module::Temperature(m) => m.format(),
-            Module::Weather(m) => m.format(),
-            Module::Wireless(m) => m.format(),
+        self.common()
+            .and_then(|c| c.format.as_deref())
+            .unwrap_or_else(|| self.module.format())
+    }
+
+    fn common(&self) -> Option<&CommonConfig> {
+        self.config.as_ref().map(|c| c.common())
+    }
+
+    fn levels(&self) -> Vec<Level> {
+        match self.common().and_then(|c| c.levels.clone()) {
+            Some(levels) => levels,
+            None => self.module.levels(self.config.as_ref()),
+        }
+    }
+
+    fn runner(&self) -> Runner {
+        let module = Arc::clone(&self.module);
+        let config = self.config.clone();
+        Box::new(move |control, tx| module.run(control, config.as_ref(), tx))
+    }
+}
+
+/// Type-erased module of the registry, building the module instances
+/// from their config section
+trait Factory: Send + Sync {
+    fn name(&self) -> &str;
+    fn build(&self, section: Option<&YamlValue>, id: Option<&str>) -> Result<Box<dyn Bar>, Error>;
+    /// Check every instance of the module, and the default config if the
+    /// module is used without being declared.
+    /// Returns the errors and the id of the failing instances
+    fn check(&self, section: Option<&YamlValue>, used: bool) -> Vec<(Option<String>, Error)>;
+    /// The config section as understood by the module, without the unknown keys
+    fn known(&self, section: &YamlValue) -> Result<YamlValue, Error>;
+}
+
+impl<M: Module> Factory for Arc<M> {
+    fn name(&self) -> &str {
+        self.as_ref().name()
+    }
+
+    fn build(&self, section: Option<&YamlValue>, id: Option<&str>) -> Result<Box<dyn Bar>, Error> {
+        let instances = Instances::<M::Config>::from_section(section)?;
+        Ok(Box::new(Bound {
+            module: Arc::clone(self),
+            config: instances.get(id)?.cloned(),
+        }))
+    }
+
+    fn check(&self, section: Option<&YamlValue>, used: bool) -> Vec<(Option<String>, Error)> {
+        let instances = match Instances::<M::Config>::from_section(section) {
+            Ok(i) => i,
+            Err(e) => return vec![(None, e)],
+        };
+        let mut results = vec![];
+        if instances.first().is_none() && used {
+            results.push((None, self.as_ref().check(None)));
+        }
+        for config in instances.iter() {
+            results.push((
+                config.id().map(String::from),
+                self.as_ref().check(Some(config)),
+            ));
+        }
+        results
+            .into_iter()
+            .filter_map(|(id, result)| Some((id, result.err()?)))
+            .collect()
+    }
+
+    fn known(&self, section: &YamlValue) -> Result<YamlValue, Error> {
+        let instances = Instances::<M::Config>::from_section(Some(section))?;
+        Ok(serde_yaml::to_value(&instances)?)
+    }
+}
+
+struct Entry {
+    /// Short markup key of the built-in modules, e.g. `a` for `%a`
+    key: Option<char>,
+    factory: Box<dyn Factory>,
+}
+
+/// The modules which can be used in the root format.
+/// The default registry holds the built-in modules, third-party modules
+/// are added with [`Registry::register`] and used as `%{name}`
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry.insert(Some('a'), battery::Battery);
+        registry.insert(Some('b'), brightness::Brightness);
+        registry.insert(Some('c'), cpu_usage::CpuUsage);
+        registry.insert(Some('d'), date_time::DateTime);
+        registry.insert(Some('e'), wired::Wired);
+        registry.insert(Some('f'), cpu_freq::CpuFreq);
+        registry.insert(Some('i'), mic::Mic);
+        registry.insert(Some('m'), memory::Memory);
+        registry.insert(Some('r'), weather::Weather);
+        registry.insert(Some('s'), sound::Sound);
+        registry.insert(Some('t'), temperature::Temperature);
+        registry.insert(Some('w'), wireless::Wireless);
+        registry.insert(Some('x'), command::CustomCommand);
+        registry.insert(Some('y'), stream::Stream);
+        registry.insert(Some('z'), script::Script);
+        registry
+    }
+}
+
+impl Registry {
+    /// An empty registry, without the built-in modules
+    pub fn new() -> Self {
+        Registry { entries: vec![] }
+    }
+
+    /// Register a module under its name, made of alphanumeric characters
+    /// and underscores. Fails if the name is already taken by a module
+    /// or by a root option of the config
+    pub fn register<M: Module>(&mut self, module: M) -> Result<(), Error> {
+        let name = module.name();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::new(format!("invalid module name \"{name}\"")));
         }
+        if ROOT_OPTIONS.contains(&name) || self.get(name).is_some() {
+            return Err(Error::new(format!("module name \"{name}\" already taken")));
+        }
+        self.insert(None, module);
+        Ok(())
+    }
+
+    /// The names of the registered modules
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| e.factory.name())
+    }
+
+    fn insert<M: Module>(&mut self, key: Option<char>, module: M) {
+        self.entries.push(Entry {
+            key,
+            factory: Box::new(Arc::new(module)),
+        });
+    }
+
+    fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.factory.name() == name)
+    }
+
+    /// Get the name of a module from its markup key
+    pub(crate) fn name(&self, key: char) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|e| e.key == Some(key))
+            .map(|e| e.factory.name())
+    }
+
+    /// Check the instances of a module, see [`Factory::check`]
+    pub(crate) fn check(
+        &self,
+        name: &str,
+        section: Option<&YamlValue>,
+        used: bool,
+    ) -> Vec<(Option<String>, Error)> {
+        self.get(name)
+            .map(|e| e.factory.check(section, used))
+            .unwrap_or_default()
+    }
+
+    /// The config section of a module without the unknown keys, `None` if
+    /// the module is unknown
+    pub(crate) fn known(
+        &self,
+        name: &str,
+        section: &YamlValue,
+    ) -> Option<Result<YamlValue, Error>> {
+        self.get(name).map(|e| e.factory.known(section))
+    }
+}
+
+/// The PulseAudio sink/source tracked by a module, if any
+fn pulse_target(name: &str) -> Option<Target> {
+    match name {
+        "sound" => Some(Target::Sink),
+        "mic" => Some(Target::Source),
+        _ => None,
     }
 }
 
@@ -154,45 +634,259 @@ pub enum ModuleState {
     Failed,
 }
 
+impl ModuleState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModuleState::NotStarted => "not_started",
+            ModuleState::Running => "running",
+            ModuleState::Finished => "finished",
+            ModuleState::Failed => "failed",
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct ModuleData<'a> {
-    pub key: char,
-    pub module: Module<'a>,
+pub struct ModuleData {
+    pub instance: Option<String>,
+    pub(crate) module: Box<dyn Bar>,
     data: Option<String>,
+    current: Data,
+    alt_format: bool,
     state: ModuleState,
     handle: Option<JoinHandle<Result<(), Error>>>,
+    control: Arc<Control>,
     failed_placeholder: String,
+    /// Dialect the value and the label are escaped for
+    output: Output,
+    /// Colors by state, `None` being the default color
+    colors: Vec<(Option<State>, Color)>,
+    /// Ramps by state, `None` being the default ramp
+    ramps: Vec<(Option<State>, Vec<String>)>,
+    /// Levels sorted by threshold, and their color
+    levels: Vec<(Level, Option<Color>)>,
+    /// Index of the level reached by the current data
+    level: Option<usize>,
+    restart: RestartPolicy,
+    /// Consecutive restarts of a failed module
+    restarts: u32,
+    last_error: Option<String>,
+    started_at: Option<Instant>,
+    restart_at: Option<Instant>,
 }
 
-impl<'a> ModuleData<'a> {
-    pub fn new(key: char, config: &'a Config) -> Result<Self> {
+impl ModuleData {
+    pub fn new(
+        name: &str,
+        instance: Option<&str>,
+        config: &Config,
+        registry: &Registry,
+    ) -> Result<Self> {
+        let entry = registry
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown module \"{name}\""))?;
+        let module = entry
+            .factory
+            .build(config.section(name), instance)
+            .map_err(|e| anyhow!("[{}] {}", name, e))?;
+        // the failed placeholder starts with the markup key, if any
+        let tag = entry.key.map_or_else(|| name.to_string(), String::from);
+        // check the value specifiers of the module formats
+        let alt_format = module.common().and_then(|c| c.alt_format.as_deref());
+        for format in [Some(module.format()), alt_format].into_iter().flatten() {
+            value::replace_value(format, None, str::to_string)
+                .map_err(|e| anyhow!("[{}] {}", module.name(), e))?;
+        }
+        if let Some(n) = module.common().and_then(|c| c.signal)
+            && n > signal::max_rt_signal()
+        {
+            return Err(anyhow!(
+                "[{}] signal {} out of range, SIGRTMIN+{} at most",
+                module.name(),
+                n,
+                signal::max_rt_signal()
+            ));
+        }
+        let common = module.common();
+        let colors = by_state(
+            common.and_then(|c| c.color.as_ref()),
+            common.and_then(|c| c.colors.as_ref()),
+        )
+        .map(|(state, c)| Ok((state, c.parse()?)))
+        .collect::<Result<_, Error>>()
+        .map_err(|e| anyhow!("[{}] {}", module.name(), e))?;
+        let ramps: Vec<(Option<State>, Vec<String>)> = by_state(
+            common.and_then(|c| c.ramp.as_ref()),
+            common.and_then(|c| c.ramps.as_ref()),
+        )
+        .map(|(state, ramp)| (state, ramp.clone()))
+        .collect();
+        if ramps.iter().any(|(_, ramp)| ramp.is_empty()) {
+            return Err(anyhow!("[{}] empty ramp", module.name()));
+        }
+        let mut levels = module
+            .levels()
+            .into_iter()
+            .map(|level| {
+                let color = level.color.as_deref().map(str::parse).transpose()?;
+                Ok((level, color))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+            .map_err(|e| anyhow!("[{}] {}", module.name(), e))?;
+        levels.sort_by(|a, b| {
+            a.0.above
+                .total_cmp(&b.0.above)
+                .then(a.0.strict.cmp(&b.0.strict))
+        });
+        let restart = RestartPolicy::from(common.and_then(|c| c.restart.as_ref()));
         Ok(ModuleData {
-            key,
-            module: Module::try_from((key, config))?,
+            instance: instance.map(|id| id.to_string()),
+            module,
             data: None,
+            current: Data::default(),
+            alt_format: false,
             state: ModuleState::NotStarted,
             handle: None,
+            control: Arc::new(Control::new()),
             failed_placeholder: config
                 .failed_icon
                 .as_ref()
-                .map(|icon| format!("{}:{}", &key, icon))
-                .unwrap_or_else(|| format!("{}:{}", &key, MODULE_FAILED_ICON)),
+                .map(|icon| format!("{}:{}", &tag, icon))
+                .unwrap_or_else(|| format!("{}:{}", &tag, MODULE_FAILED_ICON)),
+            output: config.output(),
+            colors,
+            ramps,
+            levels,
+            level: None,
+            restart,
+            restarts: 0,
+            last_error: None,
+            started_at: None,
+            restart_at: None,
         })
     }
 
-    pub fn new_data(&mut self, value: Option<&str>, label: Option<&str>) {
-        let mut module_format = self.module.format().to_string();
-        module_format = match value {
-            Some(v) => module_format.replace("%v", v),
-            None => module_format.replace("%v", ""),
+    pub fn new_data(&mut self, data: &Data) {
+        let level = self.level(data);
+        let mut data = data.clone();
+        if let Some((l, _)) = level.map(|i| &self.levels[i]) {
+            if let Some(label) = &l.label {
+                data.label = Some(label.to_string());
+            }
+            data.state = l.state.unwrap_or(data.state);
+        }
+        if self.data.is_some() && self.current == data && self.level == level {
+            return;
+        }
+        self.current = data;
+        self.level = level;
+        self.render();
+    }
+
+    /// The last level reached by the data. Only the data in the normal
+    /// state have a level, the other states, e.g. muted or charging, are kept
+    fn level(&self, data: &Data) -> Option<usize> {
+        if data.state != State::Normal {
+            return None;
+        }
+        let value = match data.percentage() {
+            Some(p) => p as f64,
+            None => data.level()?.value,
         };
-        module_format = match label {
-            Some(l) => module_format.replace("%l", l),
-            None => module_format.replace("%l", ""),
+        self.levels.iter().rposition(|(l, _)| l.reached(value))
+    }
+
+    fn format(&self) -> &str {
+        match self.module.common() {
+            Some(CommonConfig {
+                alt_format: Some(alt),
+                ..
+            }) if self.alt_format => alt,
+            _ => self.module.format(),
+        }
+    }
+
+    fn render(&mut self) {
+        let backend = self.output.backend();
+        let escape = |s: &str| backend.escape(s);
+        let label = self.current.label.as_deref().map(escape);
+        let ramp = self.ramp().map(escape);
+        // replaced in a single pass, a value containing `%l` is kept as is
+        let parts: Result<Vec<String>, Error> = self
+            .format()
+            .split("%l")
+            .map(|part| {
+                let parts: Vec<String> = part
+                    .split("%r")
+                    .map(|p| value::replace_value(p, self.current.value.as_ref(), escape))
+                    .collect::<Result<_, Error>>()?;
+                Ok(parts.join(ramp.as_deref().unwrap_or_default()))
+            })
+            .collect();
+        let module_format = match parts {
+            Ok(parts) => parts.join(label.as_deref().unwrap_or_default()),
+            Err(e) => {
+                error!("[{}] {}", self.module.name(), e);
+                self.format().to_string()
+            }
         };
         self.data = Some(module_format);
     }
 
+    /// Trigger the action bound to the given mouse button, if any
+    #[instrument(skip(self))]
+    pub fn on_click(&mut self, button: Button) {
+        let Some(action) = self.module.common().and_then(|c| c.action(button)) else {
+            return;
+        };
+        debug!("[{}] action triggered: {:?}", self.module.name(), action);
+        self.trigger(action.clone());
+    }
+
+    #[instrument(skip(self))]
+    pub fn trigger(&mut self, action: Action) {
+        match action {
+            Action::Command(command) => {
+                action::spawn_command(&command).ok();
+            }
+            Action::Builtin(Builtin::ToggleFormat) => {
+                self.alt_format = !self.alt_format;
+                if self.data.is_some() {
+                    self.render();
+                }
+            }
+            Action::Builtin(builtin) => {
+                let command = pulse_target(self.module.name())
+                    .and_then(|target| builtin.pulse_command(target));
+                match command {
+                    Some(cmd) => {
+                        pulse::send_command(cmd)
+                            .inspect_err(|e| error!("failed to send pulse command: {}", e))
+                            .ok();
+                    }
+                    None => warn!(
+                        "[{}] built-in action {:?} not supported",
+                        self.module.name(),
+                        builtin
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Whether the module has nothing to display, i.e. it has failed,
+    /// is disconnected or has neither value nor label. An empty text, e.g.
+    /// a command printing nothing, is no value
+    pub fn is_empty(&self) -> bool {
+        let no_value = match &self.current.value {
+            None => true,
+            Some(Value::Text(text)) => text.is_empty(),
+            Some(_) => false,
+        };
+        matches!(self.state, ModuleState::Failed)
+            || self.current.state == State::Disconnected
+            || (self.data.is_some() && no_value && self.current.label.is_none())
+    }
+
     pub fn output(&self) -> &str {
         if matches!(self.state, ModuleState::Failed) {
             return &self.failed_placeholder;
@@ -205,9 +899,235 @@ impl<'a> ModuleData<'a> {
         }
     }
 
+    /// Build the i3bar protocol block of the module
+    pub fn block(&self) -> Block<'_> {
+        let common = self.module.common();
+        Block {
+            name: self.module.name(),
+            instance: self.instance.as_deref(),
+            full_text: self.output(),
+            color: self.color().map(|c| c.to_string()),
+            urgent: matches!(self.state, ModuleState::Failed)
+                || self.current.state == State::Critical,
+            separator: common.and_then(|c| c.separator).unwrap_or(true),
+        }
+    }
+
+    /// The output of the module in its color, wrapped in the click areas of
+    /// its command actions. Built-in actions need the i3bar click events
+    pub fn styled(&self, backend: &dyn Backend) -> String {
+        let mut text = match self.color() {
+            Some(color) => backend.color(self.output(), color),
+            None => self.output().to_string(),
+        };
+        for button in [Button::Left, Button::ScrollUp, Button::ScrollDown] {
+            if let Some(Action::Command(command)) =
+                self.module.common().and_then(|c| c.action(button))
+            {
+                match backend.click(&text, button, command) {
+                    Some(area) => text = area,
+                    None => debug!(
+                        "[{}] no click area for the command `{}`",
+                        self.module.name(),
+                        command
+                    ),
+                }
+            }
+        }
+        text
+    }
+
+    /// The color of the current level or state of the module, or its
+    /// default color. A failed module takes the color of the critical state
+    pub fn color(&self) -> Option<&Color> {
+        let state = match self.state {
+            ModuleState::Failed => State::Critical,
+            _ => {
+                if let Some(color) = self.level.and_then(|i| self.levels[i].1.as_ref()) {
+                    return Some(color);
+                }
+                self.current.state
+            }
+        };
+        state_option(&self.colors, state)
+    }
+
+    /// The icon of the ramp of the current state picked by the percentage
+    /// of the module. Without percentage, only a ramp of a single icon is
+    /// displayed, e.g. a muted speaker
+    fn ramp(&self) -> Option<&str> {
+        let ramp = state_option(&self.ramps, self.current.state)?;
+        let index = match self.current.percentage() {
+            Some(p) => (p as usize * ramp.len() / 100).min(ramp.len() - 1),
+            None if ramp.len() == 1 => 0,
+            None => return None,
+        };
+        Some(&ramp[index])
+    }
+
+    /// The current data and the index of its level, if the module has
+    /// sent data and has not failed
+    pub(crate) fn last_data(&self) -> Option<(Data, Option<usize>)> {
+        match (&self.data, &self.state) {
+            (_, ModuleState::Failed) | (None, _) => None,
+            _ => Some((self.current.clone(), self.level)),
+        }
+    }
+
+    /// Display the data of the module before a reload, the config of the
+    /// module being the same, until the new thread sends its own
+    pub(crate) fn restore_data(&mut self, (data, level): (Data, Option<usize>)) {
+        self.current = data;
+        self.level = level;
+        self.render();
+    }
+
+    /// Whether the module has sent its first data or has stopped
+    pub fn is_ready(&self) -> bool {
+        self.data.is_some() || matches!(self.state, ModuleState::Finished | ModuleState::Failed)
+    }
+
+    /// The state of the module value, or `failed`
+    fn state_name(&self) -> &str {
+        match self.state {
+            ModuleState::Failed => "failed",
+            _ => self.current.state.as_str(),
+        }
+    }
+
+    /// Build the snapshot of the current data of the module
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
+            name: self.module.name(),
+            instance: self.instance.as_deref(),
+            text: self.output(),
+            value: self
+                .current
+                .value
+                .as_ref()
+                .map(|v| v.to_string().trim().to_string()),
+            label: self.current.label.as_deref(),
+            state: self.state_name(),
+            percentage: self.current.percentage(),
+        }
+    }
+
+    /// Build the waybar custom module output of the module
+    pub fn waybar(&self) -> WaybarOutput<'_> {
+        let class = self.state_name();
+        WaybarOutput {
+            text: self.output(),
+            alt: self.current.label.as_deref().unwrap_or_default(),
+            tooltip: format!(
+                "{}: {}",
+                self.module.name(),
+                self.current
+                    .value
+                    .as_ref()
+                    .map(|v| v.to_string())
+                    .unwrap_or_default()
+                    .trim()
+            ),
+            class,
+            percentage: self.current.percentage(),
+        }
+    }
+
+    /// The real-time signal `SIGRTMIN+n` updating the module
+    pub fn signal(&self) -> Option<u32> {
+        self.module.common().and_then(|c| c.signal)
+    }
+
+    pub fn control(&self) -> &Arc<Control> {
+        &self.control
+    }
+
+    /// Whether the module is the given instance, `None` being the default one
+    pub fn is(&self, name: &str, instance: Option<&str>) -> bool {
+        self.module.name() == name && self.instance.as_deref() == instance
+    }
+
+    /// Whether the module is the given one, an instance id of `None`
+    /// matching every instance of the module
+    pub fn matches(&self, name: &str, instance: Option<&str>) -> bool {
+        self.module.name() == name && (instance.is_none() || self.instance.as_deref() == instance)
+    }
+
+    /// Build the status of the module thread
+    pub fn status(&self) -> ModuleStatus<'_> {
+        let state = match self.state {
+            ModuleState::Running if self.control.is_paused() => "paused",
+            ModuleState::Failed if self.restart_at.is_some() => "restarting",
+            _ => self.state.as_str(),
+        };
+        ModuleStatus {
+            name: self.module.name(),
+            instance: self.instance.as_deref(),
+            state,
+            restarts: self.restarts,
+            last_error: self.last_error.as_deref(),
+        }
+    }
+
     pub fn start(&mut self, handle: JoinHandle<Result<(), Error>>) {
         self.handle = Some(handle);
         self.state = ModuleState::Running;
+        self.started_at = Some(Instant::now());
+    }
+
+    /// Start the module again after a failure
+    pub fn restart(&mut self, handle: JoinHandle<Result<(), Error>>) {
+        self.restarts += 1;
+        self.restart_at = None;
+        self.start(handle);
+        info!(
+            "[{}] module restarted ({}/{}), last error: {}",
+            self.module.name(),
+            self.restarts,
+            self.restart.max,
+            self.last_error.as_deref().unwrap_or("unknown")
+        );
+    }
+
+    /// Whether the module thread is still running
+    pub fn is_alive(&self) -> bool {
+        self.handle.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
+    /// Whether the module has failed and its restart delay is over
+    pub fn restart_due(&self) -> bool {
+        self.restart_at.is_some_and(|at| at <= Instant::now())
+    }
+
+    /// Schedule the restart of a failed module, unless it failed too many
+    /// times in a row. A module running longer than the maximum backoff
+    /// before failing is considered recovered.
+    fn schedule_restart(&mut self) {
+        if self
+            .started_at
+            .is_some_and(|at| at.elapsed() >= self.restart.max_backoff)
+        {
+            self.restarts = 0;
+        }
+        if self.restarts >= self.restart.max {
+            if self.restart.max > 0 {
+                error!(
+                    "[{}] module failed {} times in a row, giving up",
+                    self.module.name(),
+                    self.restarts + 1
+                );
+            }
+            return;
+        }
+        let delay = self.restart.delay(self.restarts);
+        info!(
+            "[{}] restarting module in {:?} ({}/{})",
+            self.module.name(),
+            delay,
+            self.restarts + 1,
+            self.restart.max
+        );
+        self.restart_at = Some(Instant::now() + delay);
     }
 
     #[instrument(skip_all)]
@@ -233,13 +1153,18 @@ impl<'a> ModuleData<'a> {
             }
             Ok(Err(e)) => {
                 error!("[{}] module failed: {}", self.module.name(), e);
+                self.last_error = Some(e.to_string());
                 ModuleState::Failed
             }
             Err(_) => {
                 error!("[{}] module panicked", self.module.name());
+                self.last_error = Some("module panicked".to_string());
                 ModuleState::Failed
             }
         };
+        if let ModuleState::Failed = self.state {
+            self.schedule_restart();
+        }
         Ok(())
     }
 
@@ -254,3 +1179,160 @@ impl<'a> ModuleData<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    struct GaugeConfig {
+        #[serde(flatten)]
+        common: CommonConfig,
+    }
+
+    impl Instance for GaugeConfig {
+        fn common(&self) -> &CommonConfig {
+            &self.common
+        }
+    }
+
+    struct Gauge;
+
+    impl Module for Gauge {
+        type Config = GaugeConfig;
+
+        fn name(&self) -> &str {
+            "gauge"
+        }
+
+        fn run(&self, _: &Control, _: Option<&GaugeConfig>, _: &ModuleSender) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    /// Build the gauge module from the config, without starting it
+    fn gauge(yaml: &str) -> Result<ModuleData> {
+        let mut registry = Registry::default();
+        registry.register(Gauge).unwrap();
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        ModuleData::new("gauge", None, &config, &registry)
+    }
+
+    fn percent(p: f64, state: State) -> Data {
+        Data::new(Some(Value::percent(p)), None, state)
+    }
+
+    #[test]
+    fn reach_level() {
+        let level = Level::new(90.0, "!me", State::High);
+        assert!(!level.reached(89.9));
+        assert!(level.reached(90.0));
+        let level = level.strict();
+        assert!(!level.reached(90.0));
+        assert!(level.reached(90.5));
+    }
+
+    #[test]
+    fn render_colors() {
+        let mut module = gauge(
+            "format: '%{gauge}'
+output: xmobar
+gauge:
+  color: '#0000ff'
+  colors:
+    critical: '#ff0000'
+",
+        )
+        .unwrap();
+        module.new_data(&percent(42.0, State::Normal));
+        assert_eq!(module.color().unwrap().to_string(), "#0000ff");
+        module.new_data(&percent(42.0, State::Critical));
+        assert_eq!(module.color().unwrap().to_string(), "#ff0000");
+        assert_eq!(
+            module.styled(Output::Xmobar.backend()),
+            "<fc=#ff0000> 42%</fc>"
+        );
+        assert!(gauge("format: '%{gauge}'\ngauge:\n  color: red\n").is_err());
+    }
+
+    #[test]
+    fn render_escaped() {
+        let mut module = gauge(
+            "format: '%{gauge}'
+output: lemonbar
+gauge:
+  format: '%l %v%%'
+  on_click:
+    command: 'echo a:b'
+",
+        )
+        .unwrap();
+        module.new_data(&Data::new(
+            Some(Value::Text("50%".to_string())),
+            Some("%l"),
+            State::Normal,
+        ));
+        assert_eq!(module.output(), "%%l 50%%%%");
+        assert_eq!(
+            module.styled(Output::Lemonbar.backend()),
+            "%{A1:echo a\\:b:}%%l 50%%%%%{A}"
+        );
+    }
+
+    #[test]
+    fn render_levels() {
+        let mut module = gauge(
+            "format: '%{gauge}'
+gauge:
+  format: '%l%v'
+  levels:
+    - above: 50
+      label: hi
+      color: '#ff0000'
+      state: critical
+    - above: 10
+      label: lo
+",
+        )
+        .unwrap();
+        module.new_data(&percent(60.0, State::Normal));
+        assert_eq!(module.output(), "hi 60%");
+        assert_eq!(module.color().unwrap().to_string(), "#ff0000");
+        assert!(module.block().urgent);
+        module.new_data(&percent(30.0, State::Normal));
+        assert_eq!(module.output(), "lo 30%");
+        assert_eq!(module.color(), None);
+        module.new_data(&percent(5.0, State::Normal));
+        assert_eq!(module.output(), "  5%");
+        // the other states keep their label
+        module.new_data(&percent(60.0, State::Muted));
+        assert_eq!(module.output(), " 60%");
+        assert!(!module.block().urgent);
+    }
+
+    #[test]
+    fn render_ramps() {
+        let mut module = gauge(
+            "format: '%{gauge}'
+gauge:
+  format: '%r%v'
+  ramp: ['1', '2', '3', '4', '5']
+  ramps:
+    muted: ['!']
+",
+        )
+        .unwrap();
+        let mut ramp = |data: Data| {
+            module.new_data(&data);
+            module.ramp().map(str::to_string)
+        };
+        assert_eq!(ramp(percent(0.0, State::Normal)).as_deref(), Some("1"));
+        assert_eq!(ramp(percent(60.0, State::Normal)).as_deref(), Some("4"));
+        assert_eq!(ramp(percent(100.0, State::Normal)).as_deref(), Some("5"));
+        assert_eq!(ramp(percent(30.0, State::Muted)).as_deref(), Some("!"));
+        let text = Data::new(Some(Value::Text("a".to_string())), None, State::Normal);
+        assert_eq!(ramp(text), None);
+        assert_eq!(module.output(), "a");
+        assert!(gauge("format: '%{gauge}'\ngauge:\n  ramp: []\n").is_err());
+    }
+}
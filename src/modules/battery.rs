@@ -2,16 +2,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::module::{CommonConfig, Instance, Level, Module, ModuleSender};
+use crate::value::{Data, State, Value};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{self, BufReader, prelude::*};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument};
 
@@ -42,18 +40,23 @@ pub struct Config {
     low_level: Option<u32>,
     full_design: Option<bool>,
     tick: Option<u32>,
-    placeholder: Option<String>,
     full_label: Option<String>,
     charging_label: Option<String>,
     discharging_label: Option<String>,
     low_label: Option<String>,
     unknown_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
 pub struct InternalConfig<'a> {
-    low_level: u32,
     tick: Duration,
     uevent: String,
     now_attribute: String,
@@ -61,34 +64,26 @@ pub struct InternalConfig<'a> {
     full_label: &'a str,
     charging_label: &'a str,
     discharging_label: &'a str,
-    low_label: &'a str,
     unknown_label: &'a str,
 }
 
-impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
     type Error = Error;
 
-    fn try_from(config: &'a MainConfig) -> Result<Self, Self::Error> {
-        let mut low_level = LOW_LEVEL;
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
         let mut name = BATTERY_NAME;
         let mut full_design = FULL_DESIGN;
         let mut tick = TICK_RATE;
         let mut full_label = FULL_LABEL;
         let mut charging_label = CHARGING_LABEL;
         let mut discharging_label = DISCHARGING_LABEL;
-        let mut low_label = LOW_LABEL;
         let mut unknown_label = UNKNOWN_LABEL;
-        if let Some(c) = &config.battery {
+        if let Some(c) = config {
             if let Some(n) = &c.name {
                 name = n;
             }
-            if let Some(v) = &c.low_level {
-                low_level = *v;
-            }
-            if let Some(b) = c.full_design {
-                if b {
-                    full_design = true;
-                }
+            if let Some(true) = c.full_design {
+                full_design = true;
             }
             if let Some(t) = c.tick {
                 tick = Duration::from_millis(t as u64)
@@ -102,9 +97,6 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
             if let Some(v) = &c.discharging_label {
                 discharging_label = v;
             }
-            if let Some(v) = &c.low_label {
-                low_label = v;
-            }
             if let Some(v) = &c.unknown_label {
                 unknown_label = v;
             }
@@ -116,7 +108,6 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
         let uevent = format!("{}{}/{}", SYS_PATH, &name, UEVENT);
         let attribute_prefix = find_attribute_prefix(&uevent)?;
         Ok(InternalConfig {
-            low_level,
             tick,
             uevent,
             now_attribute: format!("{POWER_SUPPLY}_{attribute_prefix}_{NOW_ATTRIBUTE}"),
@@ -124,67 +115,75 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
             full_label,
             charging_label,
             discharging_label,
-            low_label,
             unknown_label,
         })
     }
 }
 
 #[derive(Debug)]
-pub struct Battery<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Battery;
 
-impl<'a> Battery<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.battery {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Battery {
-            format,
-            placeholder,
-        }
-    }
-}
+impl Module for Battery {
+    type Config = Config;
 
-impl<'a> Bar for Battery<'a> {
     fn name(&self) -> &str {
         "battery"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn levels(&self, config: Option<&Config>) -> Vec<Level> {
+        levels(config)
+    }
+
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
+/// The default levels, the battery being low up to `low_level`: the
+/// second level restores the label sent by the module above it
+fn levels(config: Option<&Config>) -> Vec<Level> {
+    let low_level = config.and_then(|c| c.low_level).unwrap_or(LOW_LEVEL);
+    let low_label = config.and_then(|c| c.low_label.as_deref());
+    vec![
+        Level::new(0.0, low_label.unwrap_or(LOW_LABEL), State::Low),
+        Level {
+            above: low_level.into(),
+            state: Some(State::Normal),
+            ..Level::default()
+        }
+        .strict(),
+    ]
+}
+
+/// Check the module config, as done when the module starts
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    InternalConfig::try_from(config).map(|_| ())
+}
+
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::try_from(&main_config)?;
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let (energy, capacity, status) = parse_attributes(
             &config.uevent,
@@ -194,27 +193,19 @@ pub fn run(
         let capacity = capacity as u64;
         let energy = energy as u64;
         let battery_level = u32::try_from(100_u64 * energy / capacity)?;
-        let label = match status.as_str() {
-            "Full" => config.full_label,
-            "Discharging" => {
-                if battery_level <= config.low_level {
-                    config.low_label
-                } else {
-                    config.discharging_label
-                }
-            }
-            "Charging" => config.charging_label,
-            _ => config.unknown_label,
+        let (label, state) = match status.as_str() {
+            "Full" => (config.full_label, State::Full),
+            "Discharging" => (config.discharging_label, State::Normal),
+            "Charging" => (config.charging_label, State::Charging),
+            _ => (config.unknown_label, State::Unknown),
         };
-        tx.send(ModuleMsg(
-            key,
-            Some(format!("{battery_level:3}%")),
-            Some(label.to_string()),
+        tx.send(Data::new(
+            Some(Value::percent(battery_level.into())),
+            Some(label),
+            state,
         ))?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
@@ -224,7 +215,7 @@ fn parse_attributes(
     now_attribute: &str,
     full_attribute: &str,
 ) -> Result<(i32, i32, String), Error> {
-    let file = File::open(uevent)?;
+    let file = File::open(uevent).map_err(|e| Error::new(format!("{uevent}: {e}")))?;
     let f = BufReader::new(file);
     let mut now = None;
     let mut full = None;
@@ -249,28 +240,26 @@ fn parse_attributes(
 }
 
 fn parse_attribute(line: &io::Result<String>, attribute: &str) -> Option<i32> {
-    if let Ok(l) = line {
-        if l.starts_with(attribute) {
-            let s = l.split('=').nth(1);
-            if let Some(v) = s {
-                return v.parse::<i32>().ok();
-            }
-        }
+    if let Ok(l) = line
+        && l.starts_with(attribute)
+        && let Some(v) = l.split('=').nth(1)
+    {
+        return v.parse::<i32>().ok();
     }
     None
 }
 
 fn parse_status(line: &io::Result<String>) -> Option<String> {
-    if let Ok(l) = line {
-        if l.starts_with(STATUS_ATTRIBUTE) {
-            return l.split('=').nth(1).map(|s| s.to_string());
-        }
+    if let Ok(l) = line
+        && l.starts_with(STATUS_ATTRIBUTE)
+    {
+        return l.split('=').nth(1).map(|s| s.to_string());
     }
     None
 }
 
 fn find_attribute_prefix<'e>(path: &str) -> Result<&'e str, Error> {
-    let content = fs::read_to_string(path)?;
+    let content = fs::read_to_string(path).map_err(|e| Error::new(format!("{path}: {e}")))?;
     let mut unit = None;
     if content.contains(&format!(
         "{POWER_SUPPLY}_{ENERGY_PREFIX}_{FULL_DESIGN_ATTRIBUTE}="
@@ -287,3 +276,24 @@ fn find_attribute_prefix<'e>(path: &str) -> Result<&'e str, Error> {
     }
     unit.ok_or_else(|| Error::new(format!("unable to find the required attributes in {path}")))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config as RootConfig;
+    use crate::module::{ModuleData, Registry};
+
+    #[test]
+    fn low_level_while_discharging() {
+        let config: RootConfig =
+            serde_yaml::from_str("format: '%a'\nbattery:\n  low_level: 20\n").unwrap();
+        let mut battery = ModuleData::new("battery", None, &config, &Registry::default()).unwrap();
+        let data = |label, state| Data::new(Some(Value::percent(5.0)), Some(label), state);
+        battery.new_data(&data(DISCHARGING_LABEL, State::Normal));
+        assert_eq!(battery.output(), "!ba:  5%");
+        battery.new_data(&data(UNKNOWN_LABEL, State::Unknown));
+        assert_eq!(battery.output(), ".ba:  5%");
+        battery.new_data(&data(CHARGING_LABEL, State::Charging));
+        assert_eq!(battery.output(), "^ba:  5%");
+    }
+}
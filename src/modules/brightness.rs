@@ -2,14 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
 use crate::util::read_and_parse;
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, State, Value};
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
+use std::path::Path;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument};
 
@@ -21,11 +20,17 @@ const FORMAT: &str = "%l:%v";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
-    placeholder: Option<String>,
     sys_path: Option<String>,
     tick: Option<u32>,
     label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,12 +40,12 @@ pub struct InternalConfig<'a> {
     label: &'a str,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut sys_path = SYS_PATH;
         let mut tick = TICK_RATE;
         let mut label = LABEL;
-        if let Some(c) = &config.brightness {
+        if let Some(c) = config {
             if let Some(v) = &c.sys_path {
                 sys_path = v;
             }
@@ -60,73 +65,67 @@ impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct Brightness<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Brightness;
 
-impl<'a> Brightness<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.brightness {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Brightness {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for Brightness {
+    type Config = Config;
 
-impl<'a> Bar for Brightness<'a> {
     fn name(&self) -> &str {
         "brightness"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
+/// Check the module config, as done when the module starts
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    let config = InternalConfig::from(config);
+    if !Path::new(config.sys_path).is_dir() {
+        return Err(Error::new(format!(
+            "sys_path \"{}\" not found",
+            config.sys_path
+        )));
+    }
+    Ok(())
+}
+
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let brightness = read_and_parse(&format!("{}/actual_brightness", config.sys_path))?;
         let max_brightness = read_and_parse(&format!("{}/max_brightness", config.sys_path))?;
         let percentage = 100 * brightness / max_brightness;
-        tx.send(ModuleMsg(
-            key,
-            Some(format!("{percentage:3}%")),
-            Some(config.label.to_string()),
+        tx.send(Data::new(
+            Some(Value::percent(percentage.into())),
+            Some(config.label),
+            State::Normal,
         ))?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
@@ -0,0 +1,184 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::control::Control;
+use crate::error::Error;
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
+use crate::util;
+use crate::value::{Data, State, Value};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument};
+
+const PLACEHOLDER: &str = "-";
+const TICK_RATE: Duration = Duration::from_millis(10_000);
+const TIMEOUT: Duration = Duration::from_millis(5000);
+const FORMAT: &str = "%v";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    command: String,
+    // Update interval in milliseconds
+    tick: Option<u32>,
+    // Maximum run time of the command in milliseconds
+    timeout: Option<u32>,
+    label: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
+}
+
+#[derive(Debug)]
+pub struct InternalConfig<'a> {
+    command: &'a str,
+    tick: Duration,
+    timeout: Duration,
+    label: Option<&'a str>,
+}
+
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
+    type Error = Error;
+
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
+        let c = config.ok_or("missing command config")?;
+        if c.command.trim().is_empty() {
+            return Err(Error::new("empty command"));
+        }
+        Ok(InternalConfig {
+            command: &c.command,
+            tick: c
+                .tick
+                .map_or(TICK_RATE, |t| Duration::from_millis(t as u64)),
+            timeout: c
+                .timeout
+                .map_or(TIMEOUT, |t| Duration::from_millis(t as u64)),
+            label: c.label.as_deref(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CustomCommand;
+
+impl Module for CustomCommand {
+    type Config = Config;
+
+    fn name(&self) -> &str {
+        "command"
+    }
+
+    fn format(&self) -> &str {
+        FORMAT
+    }
+
+    fn placeholder(&self) -> &str {
+        PLACEHOLDER
+    }
+
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
+    }
+}
+
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    InternalConfig::try_from(config).map(|_| ())
+}
+
+#[instrument(skip_all)]
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
+    debug!("{:#?}", config);
+    let mut iteration_start: Instant;
+    let mut iteration_end: Duration;
+    while control.is_running() {
+        iteration_start = Instant::now();
+        if let Some((status, output)) = run_command(control, config.command, config.timeout)? {
+            let mut lines = output.lines();
+            let value = lines.next().unwrap_or_default().trim_end().to_string();
+            let label = lines.next().map(str::trim_end).or(config.label);
+            tx.send(Data::new(
+                Some(Value::Text(value)),
+                label,
+                exit_state(status),
+            ))?;
+        }
+        iteration_end = iteration_start.elapsed();
+        control.sleep(config.tick.saturating_sub(iteration_end));
+    }
+    Ok(())
+}
+
+/// Run the command in a shell and get its exit status and output.
+/// Returns `None` if the command has been killed, on timeout or once
+/// the module is stopped
+fn run_command(
+    control: &Control,
+    command: &str,
+    timeout: Duration,
+) -> Result<Option<(ExitStatus, String)>, Error> {
+    let result = util::run_command(command, timeout, || !control.is_running())?;
+    Ok(result.filter(|(s, _)| s.signal().is_none()))
+}
+
+/// Exit status of the command to the state of the module:
+/// 0 normal, 1 warning, any other code critical
+fn exit_state(status: ExitStatus) -> State {
+    match status.code() {
+        Some(0) => State::Normal,
+        Some(1) => State::Warning,
+        _ => State::Critical,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_output() {
+        let control = Control::new();
+        let (status, output) = run_command(&control, "printf '42\\nlabel'; exit 1", TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(output, "42\nlabel");
+        assert_eq!(exit_state(status), State::Warning);
+    }
+
+    #[test]
+    fn command_timeout() {
+        let control = Control::new();
+        let start = Instant::now();
+        let result = run_command(&control, "sleep 10 & wait", Duration::from_millis(100));
+        assert!(result.unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn command_background_child() {
+        let control = Control::new();
+        let start = Instant::now();
+        let (_, output) = run_command(&control, "sleep 10 & echo x", TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(output, "x\n");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -2,15 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Level, Module, ModuleSender};
 use crate::util::read_and_parse;
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, Number, State, Value};
 use serde::{Deserialize, Serialize};
 use std::fs::{DirEntry, read_dir};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use std::{convert::TryFrom, path::Path};
 use tracing::{debug, instrument};
@@ -35,10 +33,16 @@ pub struct Config {
     unit: Option<Unit>,
     max_freq: Option<bool>,
     high_level: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
     high_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
@@ -50,33 +54,26 @@ enum Unit {
 
 #[derive(Debug)]
 pub struct InternalConfig<'a> {
-    high_level: u32,
     tick: Duration,
     max_freq: f32,
     unit: Unit,
     show_max_freq: bool,
     label: &'a str,
-    high_label: &'a str,
     cur_freq_attribute: &'a str,
 }
 
-impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
     type Error = Error;
 
-    fn try_from(config: &'a MainConfig) -> Result<Self, Self::Error> {
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
         let mut tick = TICK_RATE;
         let mut show_max_freq = MAX_FREQ;
         let mut unit = UNIT;
-        let mut high_level = HIGH_LEVEL;
         let mut label = LABEL;
-        let mut high_label = HIGH_LABEL;
-        if let Some(c) = &config.cpu_freq {
+        if let Some(c) = config {
             if let Some(t) = c.tick {
                 tick = Duration::from_millis(t as u64)
             }
-            if let Some(c) = c.high_level {
-                high_level = c;
-            }
             if let Some(v) = c.max_freq {
                 show_max_freq = v;
             }
@@ -86,9 +83,6 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
             if let Some(v) = &c.label {
                 label = v;
             }
-            if let Some(v) = &c.high_label {
-                high_label = v;
-            }
         };
         let policy_path = format!("{SYSFS_CPUFREQ}/policy0");
         let entries: Vec<DirEntry> = read_dir(Path::new(&policy_path))?
@@ -122,73 +116,76 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
             return Err(Error::new("fail to find max cpu freq"));
         };
         Ok(InternalConfig {
-            high_level,
             tick,
             show_max_freq,
             max_freq: (max_freq / 1000) as f32,
             unit,
             label,
-            high_label,
             cur_freq_attribute,
         })
     }
 }
 
 #[derive(Debug)]
-pub struct CpuFreq<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct CpuFreq;
 
-impl<'a> CpuFreq<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.cpu_freq {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        CpuFreq {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for CpuFreq {
+    type Config = Config;
 
-impl<'a> Bar for CpuFreq<'a> {
     fn name(&self) -> &str {
         "cpu_freq"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn levels(&self, config: Option<&Config>) -> Vec<Level> {
+        levels(config)
+    }
+
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
     }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
+    }
+}
+
+/// Check the module config, as done when the module starts
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    InternalConfig::try_from(config).map(|_| ())
+}
+
+/// The default level, the frequency being high from `high_level` percent of
+/// the maximum frequency
+fn levels(config: Option<&Config>) -> Vec<Level> {
+    let high_level = config.and_then(|c| c.high_level).unwrap_or(HIGH_LEVEL);
+    let high_label = config.and_then(|c| c.high_label.as_deref());
+    vec![Level::new(
+        high_level.into(),
+        high_label.unwrap_or(HIGH_LABEL),
+        State::High,
+    )]
 }
 
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::try_from(&main_config)?;
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let freqs: Vec<f32> = read_dir(Path::new(SYSFS_CPUFREQ))?
             .filter_map(|entry| entry.ok())
@@ -203,38 +200,32 @@ pub fn run(
             .collect();
         let avg = freqs.iter().sum::<f32>() / freqs.len() as f32;
         let value = match config.show_max_freq {
-            true => format!(
-                "{}/{}",
+            true => Value::Fraction(
                 humanize(avg, config.unit),
-                humanize(config.max_freq, config.unit)
+                humanize(config.max_freq, config.unit),
             ),
-            false => humanize(avg, config.unit),
+            false => Value::Number(humanize(avg, config.unit)),
         };
-        let percentage = ((avg * 100f32) / config.max_freq).round() as u32;
-        let label = if percentage >= config.high_level {
-            config.high_label
-        } else {
-            config.label
-        };
-        tx.send(ModuleMsg(key, Some(value), Some(label.to_string())))?;
+        tx.send(Data {
+            level: Some(Number::new(avg.into(), "MHz", 4, 0).with_max(config.max_freq.into())),
+            ..Data::new(Some(value), Some(config.label), State::Normal)
+        })?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
 
-fn humanize(average: f32, unit: Unit) -> String {
+fn humanize(average: f32, unit: Unit) -> Number {
     match unit {
-        Unit::GHz => format!("{:3.1}GHz", average / 1000f32),
-        Unit::MHz => format!("{:4}MHz", average.round() as u32),
+        Unit::GHz => Number::new((average / 1000f32).into(), "GHz", 3, 1),
+        Unit::MHz => Number::new(average.round().into(), "MHz", 4, 0),
         Unit::Smart => {
             let rounded = average.round();
             if rounded < 1000f32 {
-                format!("{:3}MHz", rounded as u32)
+                Number::new(rounded.into(), "MHz", 3, 0)
             } else {
-                format!("{:3.1}GHz", average / 1000f32)
+                Number::new((average / 1000f32).into(), "GHz", 3, 1)
             }
         }
     }
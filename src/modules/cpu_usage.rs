@@ -2,16 +2,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::module::{CommonConfig, Instance, Level, Module, ModuleSender};
+use crate::value::{Data, State, Value};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument};
 
@@ -27,108 +25,97 @@ const FORMAT: &str = "%l:%v";
 pub struct Config {
     tick: Option<u32>,
     high_level: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
     high_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
 pub struct InternalConfig<'a> {
     proc_stat: &'a str,
-    high_level: u32,
     tick: Duration,
     label: &'a str,
-    high_label: &'a str,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut tick = TICK_RATE;
-        let mut high_level = HIGH_LEVEL;
         let mut label = LABEL;
-        let mut high_label = HIGH_LABEL;
-        if let Some(c) = &config.cpu_usage {
+        if let Some(c) = config {
             if let Some(t) = c.tick {
                 tick = Duration::from_millis(t as u64)
             }
-            if let Some(c) = c.high_level {
-                high_level = c;
-            }
             if let Some(v) = &c.label {
                 label = v;
             }
-            if let Some(v) = &c.high_label {
-                high_label = v;
-            }
         };
         InternalConfig {
-            high_level,
             proc_stat: PROC_STAT,
             tick,
             label,
-            high_label,
         }
     }
 }
 
 #[derive(Debug)]
-pub struct CpuUsage<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct CpuUsage;
 
-impl<'a> CpuUsage<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.cpu_usage {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        CpuUsage {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for CpuUsage {
+    type Config = Config;
 
-impl<'a> Bar for CpuUsage<'a> {
     fn name(&self) -> &str {
         "cpu_usage"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn levels(&self, config: Option<&Config>) -> Vec<Level> {
+        levels(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
+/// The default level, the module being high from `high_level`
+fn levels(config: Option<&Config>) -> Vec<Level> {
+    let high_level = config.and_then(|c| c.high_level).unwrap_or(HIGH_LEVEL);
+    let high_label = config.and_then(|c| c.high_label.as_deref());
+    vec![Level::new(
+        high_level.into(),
+        high_label.unwrap_or(HIGH_LABEL),
+        State::High,
+    )]
+}
+
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mut prev_idle = 0;
     let mut prev_total = 0;
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let proc_stat = File::open(config.proc_stat)?;
         let mut reader = BufReader::new(proc_stat);
@@ -150,19 +137,13 @@ pub fn run(
         let usage = (100_f32 * (diff_total - diff_idle) as f32 / diff_total as f32).round() as i32;
         prev_total = total;
         prev_idle = idle;
-        let mut label = config.label;
-        if usage >= config.high_level as i32 {
-            label = config.high_label;
-        }
-        tx.send(ModuleMsg(
-            key,
-            Some(format!("{usage:3}%")),
-            Some(label.to_string()),
+        tx.send(Data::new(
+            Some(Value::percent(usage.into())),
+            Some(config.label),
+            State::Normal,
         ))?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
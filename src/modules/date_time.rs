@@ -2,14 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
+use crate::value::{Data, State, Value};
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument};
 
@@ -22,9 +20,15 @@ const FORMAT: &str = "%v";
 pub struct Config {
     date_format: Option<String>,
     tick: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
@@ -34,12 +38,12 @@ pub struct InternalConfig<'a> {
     label: Option<&'a str>,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut tick = TICK_RATE;
         let mut date_format = DATE_FORMAT;
         let mut label = None;
-        if let Some(c) = &config.date_time {
+        if let Some(c) = config {
             if let Some(d) = &c.date_format {
                 date_format = d;
             }
@@ -57,70 +61,50 @@ impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct DateTime<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct DateTime;
 
-impl<'a> DateTime<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.date_time {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        DateTime {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for DateTime {
+    type Config = Config;
 
-impl<'a> Bar for DateTime<'a> {
     fn name(&self) -> &str {
         "date_time"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
-        tx.send(ModuleMsg(
-            key,
-            Some(Local::now().format(config.date_format).to_string()),
-            config.label.map(|v| v.to_string()),
+        tx.send(Data::new(
+            Some(Value::Text(
+                Local::now().format(config.date_format).to_string(),
+            )),
+            config.label,
+            State::Normal,
         ))?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
@@ -2,15 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Level, Module, ModuleSender};
 use crate::util::read_and_trim;
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, Number, State, Value};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument};
 
@@ -35,33 +33,32 @@ pub struct Config {
     high_level: Option<u32>,
     display: Option<Display>,
     tick: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
     high_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
 pub struct InternalConfig<'a> {
     meminfo: &'a str,
-    high_level: u32,
     display: Display,
     tick: Duration,
     label: &'a str,
-    high_label: &'a str,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
-        let mut high_level = HIGH_LEVEL;
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut display = DISPLAY;
         let mut tick = TICK_RATE;
         let mut label = LABEL;
-        let mut high_label = HIGH_LABEL;
-        if let Some(c) = &config.memory {
-            if let Some(v) = &c.high_level {
-                high_level = *v;
-            }
+        if let Some(c) = config {
             if let Some(v) = c.display {
                 display = v;
             }
@@ -71,61 +68,45 @@ impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
             if let Some(v) = &c.label {
                 label = v;
             }
-            if let Some(v) = &c.high_label {
-                high_label = v;
-            }
         };
         InternalConfig {
             meminfo: MEMINFO,
-            high_level,
             display,
             tick,
             label,
-            high_label,
         }
     }
 }
 
 #[derive(Debug)]
-pub struct Memory<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Memory;
 
-impl<'a> Memory<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.memory {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Memory {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for Memory {
+    type Config = Config;
 
-impl<'a> Bar for Memory<'a> {
     fn name(&self) -> &str {
         "memory"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn levels(&self, config: Option<&Config>) -> Vec<Level> {
+        levels(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
@@ -150,19 +131,28 @@ impl MemRegex {
     }
 }
 
+/// The default level, the memory being high above `high_level`
+fn levels(config: Option<&Config>) -> Vec<Level> {
+    let high_level = config.and_then(|c| c.high_level).unwrap_or(HIGH_LEVEL);
+    let high_label = config.and_then(|c| c.high_label.as_deref());
+    vec![
+        Level::new(
+            high_level.into(),
+            high_label.unwrap_or(HIGH_LABEL),
+            State::High,
+        )
+        .strict(),
+    ]
+}
+
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mem_regex = MemRegex::new();
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let meminfo = read_and_trim(config.meminfo)?;
         let total_kib = find_meminfo(
@@ -192,56 +182,44 @@ pub fn run(
         )?;
         let used_kib = total_kib - free - buffers - cached - s_reclaimable;
         let percentage = (used_kib as f64 * 100_f64 / total_kib as f64).round() as i32;
-        let mut total = "".to_string();
-        let mut used = "".to_string();
-        match config.display {
+        let value = match config.display {
             Display::GB => {
                 let total_go = (1024_f32 * (total_kib as f32)) / 1_000_000_000_f32;
                 let total_mo = total_go * 10i32.pow(3) as f32;
-                total = humanize(total_go, total_mo, "GB", "MB");
                 let used_go = 1024_f32 * (used_kib as f32) / 1_000_000_000_f32;
                 let used_mo = used_go * 10i32.pow(3) as f32;
-                used = humanize(used_go, used_mo, "GB", "MB");
+                Value::Fraction(
+                    humanize(used_go, used_mo, "GB", "MB"),
+                    humanize(total_go, total_mo, "GB", "MB"),
+                )
             }
             Display::GiB => {
                 let total_gio = total_kib as f32 / 2i32.pow(20) as f32;
                 let total_mio = total_kib as f32 / 2i32.pow(10) as f32;
-                total = humanize(total_gio, total_mio, "GiB", "MiB");
                 let used_gio = used_kib as f32 / 2i32.pow(20) as f32;
                 let used_mio = used_kib as f32 / 2i32.pow(10) as f32;
-                used = humanize(used_gio, used_mio, "GiB", "MiB");
+                Value::Fraction(
+                    humanize(used_gio, used_mio, "GiB", "MiB"),
+                    humanize(total_gio, total_mio, "GiB", "MiB"),
+                )
             }
-            _ => {}
-        }
-        let mut label = config.label;
-        if percentage > config.high_level as i32 {
-            label = config.high_label;
-        }
-        match config.display {
-            Display::GB | Display::GiB => tx.send(ModuleMsg(
-                key,
-                Some(format!("{used}/{total}")),
-                Some(label.to_string()),
-            ))?,
-            Display::Percentage => tx.send(ModuleMsg(
-                key,
-                Some(format!("{percentage:3}%")),
-                Some(label.to_string()),
-            ))?,
+            Display::Percentage => Value::percent(percentage.into()),
         };
+        tx.send(Data {
+            level: Some(Number::percent(percentage.into())),
+            ..Data::new(Some(value), Some(config.label), State::Normal)
+        })?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
 
-fn humanize<'a>(v1: f32, v2: f32, u1: &'a str, u2: &'a str) -> String {
+fn humanize(v1: f32, v2: f32, u1: &str, u2: &str) -> Number {
     if v1 >= 1.0 {
-        format!("{v1:4.1}{u1}")
+        Number::new(v1.into(), u1, 4, 1)
     } else {
-        format!("{v2:4.0}{u2}")
+        Number::new(v2.into(), u2, 4, 0)
     }
 }
 
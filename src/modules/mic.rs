@@ -2,14 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
 use crate::pulse::PULSE;
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, State, Value};
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, instrument};
 
@@ -23,10 +21,16 @@ const FORMAT: &str = "%l:%v";
 pub struct Config {
     pub source_name: Option<String>,
     tick: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
     mute_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
@@ -36,12 +40,12 @@ pub struct InternalConfig<'a> {
     mute_label: &'a str,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut tick = TICK_RATE;
         let mut label = LABEL;
         let mut mute_label = MUTE_LABEL;
-        if let Some(c) = &config.mic {
+        if let Some(c) = config {
             if let Some(t) = c.tick {
                 tick = Duration::from_millis(t as u64)
             }
@@ -61,61 +65,41 @@ impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct Mic<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Mic;
 
-impl<'a> Mic<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.mic {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Mic {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for Mic {
+    type Config = Config;
 
-impl<'a> Bar for Mic<'a> {
     fn name(&self) -> &str {
         "mic"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
     let pulse = PULSE.get().ok_or("pulse module not initialized")?;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         if let Some(data) = pulse
             .lock()
@@ -125,20 +109,18 @@ pub fn run(
             })?
             .source_data()
         {
-            let label = match data.1 {
-                true => config.mute_label,
-                false => config.label,
+            let (label, state) = match data.1 {
+                true => (config.mute_label, State::Muted),
+                false => (config.label, State::Normal),
             };
-            tx.send(ModuleMsg(
-                key,
-                Some(format!("{:3}%", data.0)),
-                Some(label.to_string()),
+            tx.send(Data::new(
+                Some(Value::percent(data.0.into())),
+                Some(label),
+                state,
             ))?;
         }
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
@@ -4,12 +4,15 @@
 
 pub mod battery;
 pub mod brightness;
+pub mod command;
 pub mod cpu_freq;
 pub mod cpu_usage;
 pub mod date_time;
 pub mod memory;
 pub mod mic;
+pub mod script;
 pub mod sound;
+pub mod stream;
 pub mod temperature;
 pub mod weather;
 pub mod wired;
@@ -0,0 +1,383 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::control::Control;
+use crate::error::Error;
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
+use crate::util;
+use crate::value::{Data, Number, State, Value};
+use rhai::{AST, Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs;
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument};
+
+const PLACEHOLDER: &str = "-";
+const TICK_RATE: Duration = Duration::from_millis(1000);
+const MAX_OPERATIONS: u64 = 1_000_000;
+const TIMEOUT: Duration = Duration::from_millis(5000);
+const FORMAT: &str = "%v";
+// name of the map kept across the runs of the script
+const STORE: &str = "store";
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    script: Option<String>,
+    path: Option<String>,
+    tick: Option<u32>,
+    max_operations: Option<u64>,
+    // Maximum run time of the commands in milliseconds
+    timeout: Option<u32>,
+    label: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
+}
+
+#[derive(Debug)]
+pub struct InternalConfig<'a> {
+    source: String,
+    tick: Duration,
+    max_operations: u64,
+    timeout: Duration,
+    label: Option<&'a str>,
+}
+
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
+    type Error = Error;
+
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
+        let c = config.ok_or("missing script config")?;
+        let source = match (&c.script, &c.path) {
+            (Some(script), None) => script.to_string(),
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| Error::new(format!("failed to read script {path}: {e}")))?,
+            _ => return Err(Error::new("either script or path is required")),
+        };
+        Ok(InternalConfig {
+            source,
+            tick: c
+                .tick
+                .map_or(TICK_RATE, |t| Duration::from_millis(t as u64)),
+            max_operations: c.max_operations.unwrap_or(MAX_OPERATIONS),
+            timeout: c
+                .timeout
+                .map_or(TIMEOUT, |t| Duration::from_millis(t as u64)),
+            label: c.label.as_deref(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Script;
+
+impl Module for Script {
+    type Config = Config;
+
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn format(&self) -> &str {
+        FORMAT
+    }
+
+    fn placeholder(&self) -> &str {
+        PLACEHOLDER
+    }
+
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
+    }
+}
+
+/// Check the module config and compile the script
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    let config = InternalConfig::try_from(config)?;
+    compile(
+        &engine(config.max_operations, Shell::new(config.timeout)),
+        &config.source,
+    )
+    .map(|_| ())
+}
+
+#[instrument(skip_all)]
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
+    debug!("{:#?}", config);
+    let shell = Shell {
+        timeout: config.timeout,
+        running: control.running(),
+    };
+    let engine = engine(config.max_operations, shell);
+    let ast = compile(&engine, &config.source)?;
+    let mut scope = Scope::new();
+    scope.push(STORE, Map::new());
+    let mut iteration_start: Instant;
+    let mut iteration_end: Duration;
+    while control.is_running() {
+        iteration_start = Instant::now();
+        let result = engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+            .map_err(|e| Error::new(format!("script error: {e}")))?;
+        // drop the variables of this run, only the store is kept
+        scope.rewind(1);
+        tx.send(to_data(result, config.label)?)?;
+        iteration_end = iteration_start.elapsed();
+        control.sleep(config.tick.saturating_sub(iteration_end));
+    }
+    Ok(())
+}
+
+fn compile(engine: &Engine, source: &str) -> Result<AST, Error> {
+    engine
+        .compile(source)
+        .map_err(|e| Error::new(format!("invalid script: {e}")))
+}
+
+/// Build the script engine and its helpers
+fn engine(max_operations: u64, shell: Shell) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(max_operations);
+    // stdout is the bar output
+    engine.on_print(|s| info!("script: {}", s));
+    engine.on_debug(|s, _, pos| debug!("script {}: {}", pos, s));
+    engine.register_fn("read", read);
+    engine.register_fn("read_lines", read_lines);
+    engine.register_fn("read_kv", read_kv);
+    engine.register_fn("exists", |path: &str| fs::exists(path).unwrap_or(false));
+    engine.register_fn("list_dir", list_dir);
+    let s = shell.clone();
+    engine.register_fn("run", move |command: &str| s.output(command));
+    engine.register_fn("run_status", move |command: &str| shell.status(command));
+    engine
+}
+
+/// Content of a file, trimmed
+fn read(path: &str) -> ScriptResult<String> {
+    fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .map_err(|e| format!("failed to read {path}: {e}").into())
+}
+
+fn read_lines(path: &str) -> ScriptResult<Array> {
+    let content = read(path)?;
+    Ok(content
+        .lines()
+        .map(|line| Dynamic::from(line.to_string()))
+        .collect())
+}
+
+/// Parse the `key: value` or `KEY=value` lines of a file into a map,
+/// e.g. `/proc/meminfo` or a `uevent` file of `/sys`.
+/// The values are trimmed, numbers are parsed, the unit of
+/// `/proc/meminfo` being dropped
+fn read_kv(path: &str) -> ScriptResult<Map> {
+    let content = read(path)?;
+    let mut map = Map::new();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once([':', '=']) else {
+            continue;
+        };
+        let value = value.trim();
+        let number = value.strip_suffix(" kB").unwrap_or(value);
+        let value = match (i64::from_str(number), f64::from_str(number)) {
+            (Ok(n), _) => Dynamic::from(n),
+            (_, Ok(n)) => Dynamic::from(n),
+            _ => Dynamic::from(value.to_string()),
+        };
+        map.insert(key.trim().into(), value);
+    }
+    Ok(map)
+}
+
+/// Names of the entries of a directory, sorted
+fn list_dir(path: &str) -> ScriptResult<Array> {
+    let entries = fs::read_dir(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    Ok(names.into_iter().map(Dynamic::from).collect())
+}
+
+/// Shell commands run by the script, killed on timeout or once the
+/// module is stopped
+#[derive(Debug, Clone)]
+struct Shell {
+    timeout: Duration,
+    running: Arc<AtomicBool>,
+}
+
+impl Shell {
+    fn new(timeout: Duration) -> Self {
+        Shell {
+            timeout,
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    fn run(&self, command: &str) -> ScriptResult<(ExitStatus, String)> {
+        let stop = || !self.running.load(Ordering::Relaxed);
+        util::run_command(command, self.timeout, stop)
+            .map_err(|e| format!("failed to run `{command}`: {e}"))?
+            .ok_or_else(|| format!("command `{command}` timed out or stopped").into())
+    }
+
+    /// Run a shell command and get its output, trimmed
+    fn output(&self, command: &str) -> ScriptResult<String> {
+        let (_, output) = self.run(command)?;
+        Ok(output.trim().to_string())
+    }
+
+    /// Run a shell command and get its exit code, -1 if killed by a signal
+    fn status(&self, command: &str) -> ScriptResult<i64> {
+        let (status, _) = self.run(command)?;
+        Ok(status.code().map_or(-1, i64::from))
+    }
+}
+
+/// Convert the result of the script into the module data.
+/// The script returns either a value, i.e. a string or a number, or a map
+/// `#{ value, unit, max, label, state }` with all fields optional
+fn to_data(result: Dynamic, default_label: Option<&str>) -> Result<Data, Error> {
+    let Some(mut map) = result.clone().try_cast::<Map>() else {
+        return Ok(Data::new(
+            to_value(result, "", None)?,
+            default_label,
+            State::Normal,
+        ));
+    };
+    let field = |map: &mut Map, name: &str| map.remove(name).filter(|v| !v.is_unit());
+    let unit = match field(&mut map, "unit") {
+        Some(u) => string(u, "unit")?,
+        None => String::new(),
+    };
+    let max = match field(&mut map, "max") {
+        Some(m) => Some(number(m, "max")?),
+        None => None,
+    };
+    let value = match field(&mut map, "value") {
+        Some(v) => to_value(v, &unit, max)?,
+        None => None,
+    };
+    let label = field(&mut map, "label")
+        .map(|l| string(l, "label"))
+        .transpose()?;
+    let state = match field(&mut map, "state") {
+        Some(s) => State::from_str(&string(s, "state")?)?,
+        None => State::Normal,
+    };
+    Ok(Data::new(value, label.as_deref().or(default_label), state))
+}
+
+fn to_value(value: Dynamic, unit: &str, max: Option<f64>) -> Result<Option<Value>, Error> {
+    if value.is_unit() {
+        return Ok(None);
+    }
+    if value.is_string() {
+        return Ok(Some(Value::Text(string(value, "value")?)));
+    }
+    let precision = if value.is_float() { 1 } else { 0 };
+    let mut number = Number::new(self::number(value, "value")?, unit, 0, precision);
+    if let Some(max) = max {
+        number = number.with_max(max);
+    }
+    Ok(Some(Value::Number(number)))
+}
+
+fn number(value: Dynamic, field: &str) -> Result<f64, Error> {
+    match (value.as_int(), value.as_float()) {
+        (Ok(n), _) => Ok(n as f64),
+        (_, Ok(n)) => Ok(n),
+        _ => Err(Error::new(format!(
+            "invalid {field}, expected a number, got {}",
+            value.type_name()
+        ))),
+    }
+}
+
+fn string(value: Dynamic, field: &str) -> Result<String, Error> {
+    value
+        .into_immutable_string()
+        .map(|s: ImmutableString| s.to_string())
+        .map_err(|t| Error::new(format!("invalid {field}, expected a string, got {t}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Result<Data, Error> {
+        let engine = engine(MAX_OPERATIONS, Shell::new(Duration::from_millis(100)));
+        let result = engine
+            .eval::<Dynamic>(source)
+            .map_err(|e| Error::new(e.to_string()))?;
+        to_data(result, Some("lbl"))
+    }
+
+    #[test]
+    fn script_result() {
+        let data = eval(r#""hello""#).unwrap();
+        assert_eq!(data.value, Some(Value::Text("hello".to_string())));
+        assert_eq!(data.label.as_deref(), Some("lbl"));
+        let data =
+            eval(r#"#{ value: 42, max: 84, unit: "%", label: "x", state: "warning" }"#).unwrap();
+        assert_eq!(data.value.as_ref().unwrap().to_string(), "42%");
+        assert_eq!(data.percentage(), Some(50));
+        assert_eq!(data.label.as_deref(), Some("x"));
+        assert_eq!(data.state, State::Warning);
+        assert!(eval("()").unwrap().value.is_none());
+        assert!(eval(r#"#{ state: "asleep" }"#).is_err());
+        assert!(eval("loop {}").is_err());
+    }
+
+    #[test]
+    fn script_helpers() {
+        let data = eval(r#"#{ value: read_kv("/proc/meminfo").MemTotal }"#).unwrap();
+        assert!(matches!(data.value, Some(Value::Number(_))));
+        let data = eval(r#"run("echo hi")"#).unwrap();
+        assert_eq!(data.value, Some(Value::Text("hi".to_string())));
+        assert_eq!(
+            eval(r#"run_status("exit 3")"#)
+                .unwrap()
+                .value
+                .unwrap()
+                .to_string(),
+            "3"
+        );
+        assert!(eval(r#"run("sleep 10 & wait")"#).is_err());
+    }
+
+    #[test]
+    fn stop_command() {
+        let shell = Shell::new(Duration::from_secs(10));
+        shell.running.store(false, Ordering::Relaxed);
+        let start = Instant::now();
+        assert!(shell.output("sleep 10").is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
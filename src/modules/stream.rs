@@ -0,0 +1,198 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::control::Control;
+use crate::error::Error;
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
+use crate::util::{kill_group, shell_command};
+use crate::value::{Data, State, Value};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument, warn};
+
+const PLACEHOLDER: &str = "-";
+const POLL_RATE: Duration = Duration::from_millis(100);
+const KILL_TIMEOUT: Duration = Duration::from_millis(200);
+/// Delay before spawning the command again, doubled at each quick exit
+const BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Run time after which an exit is no longer quick, the delay is reset
+const HEALTHY_RUN: Duration = Duration::from_secs(30);
+const FORMAT: &str = "%v";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    command: String,
+    label: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
+}
+
+#[derive(Debug)]
+pub struct InternalConfig<'a> {
+    command: &'a str,
+    label: Option<&'a str>,
+}
+
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
+    type Error = Error;
+
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
+        let c = config.ok_or("missing stream config")?;
+        if c.command.trim().is_empty() {
+            return Err(Error::new("empty command"));
+        }
+        Ok(InternalConfig {
+            command: &c.command,
+            label: c.label.as_deref(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Stream;
+
+impl Module for Stream {
+    type Config = Config;
+
+    fn name(&self) -> &str {
+        "stream"
+    }
+
+    fn format(&self) -> &str {
+        FORMAT
+    }
+
+    fn placeholder(&self) -> &str {
+        PLACEHOLDER
+    }
+
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
+    }
+}
+
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    InternalConfig::try_from(config).map(|_| ())
+}
+
+/// Spawn the command and send a message for each line it prints.
+/// Once the command exits, it is spawned again after a delay doubled at
+/// each quick exit, the last line staying displayed meanwhile
+#[instrument(skip_all)]
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
+    debug!("{:#?}", config);
+    let mut backoff = BACKOFF;
+    while control.is_running() {
+        let started = Instant::now();
+        let Some(status) = stream(control, &config, tx)? else {
+            break;
+        };
+        if started.elapsed() >= HEALTHY_RUN {
+            backoff = BACKOFF;
+        }
+        warn!(
+            "command `{}` exited, {}, restarting in {:?}",
+            config.command, status, backoff
+        );
+        control.sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+    Ok(())
+}
+
+/// Run the command until it exits or the module is stopped.
+/// Returns the exit status of the command, `None` once stopped
+fn stream(
+    control: &Control,
+    config: &InternalConfig,
+    tx: &ModuleSender,
+) -> Result<Option<ExitStatus>, Error> {
+    let mut child = shell_command(config.command)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    info!("command `{}` spawned", config.command);
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or("failed to get the command output")?;
+    let (lines_tx, lines) = mpsc::channel();
+    thread::Builder::new()
+        .name("stream_output".into())
+        .spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if lines_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        })?;
+    while control.is_running() {
+        match lines.recv_timeout(POLL_RATE) {
+            Ok(line) => {
+                // the lines printed while paused are dropped
+                if control.is_paused() {
+                    continue;
+                }
+                let result = tx.send(Data::new(
+                    Some(Value::Text(line.trim_end().to_string())),
+                    config.label,
+                    State::Normal,
+                ));
+                if let Err(e) = result {
+                    terminate(&mut child)?;
+                    return Err(e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                // the output is closed, the rest of the group may still run
+                terminate(&mut child)?;
+                return Ok(Some(child.wait()?));
+            }
+        }
+    }
+    terminate(&mut child)?;
+    Ok(None)
+}
+
+/// Terminate the command, killing it if it does not exit in time
+fn terminate(child: &mut Child) -> Result<(), Error> {
+    kill_group(child, libc::SIGTERM);
+    let deadline = Instant::now() + KILL_TIMEOUT;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            warn!("command still running, killing it");
+            kill_group(child, libc::SIGKILL);
+            child.wait()?;
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    info!("command terminated");
+    Ok(())
+}
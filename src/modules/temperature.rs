@@ -2,16 +2,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Level, Module, ModuleSender};
 use crate::util::read_and_parse;
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, State, Value};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use std::{fs, io};
 use tracing::{debug, instrument, warn};
@@ -39,31 +37,33 @@ pub struct Config {
     high_level: Option<u32>,
     core_inputs: Option<CoreInputs>,
     tick: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
     high_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
 pub struct InternalConfig<'a> {
     coretemp: &'a str,
-    high_level: u32,
     tick: Duration,
     inputs: Vec<u32>,
     label: &'a str,
-    high_label: &'a str,
 }
 
 impl<'a> Default for InternalConfig<'a> {
     fn default() -> Self {
         InternalConfig {
             coretemp: CORETEMP,
-            high_level: HIGH_LEVEL,
             tick: TICK_RATE,
             inputs: vec![INPUT],
             label: LABEL,
-            high_label: HIGH_LABEL,
         }
     }
 }
@@ -125,21 +125,17 @@ fn get_inputs(core_inputs: &CoreInputs, temp_dir: &str) -> Option<Vec<u32>> {
     }
 }
 
-impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
     type Error = Error;
 
-    fn try_from(config: &'a MainConfig) -> Result<Self, Self::Error> {
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
         let coretemp = config
-            .temperature
-            .as_ref()
             .and_then(|c| c.coretemp.as_deref())
             .unwrap_or(CORETEMP);
         check_dir(coretemp)?;
         let temp_dir = find_temp_dir(coretemp)?;
 
         let internal_cfg = config
-            .temperature
-            .as_ref()
             .map(|c| {
                 let inputs = c
                     .core_inputs
@@ -156,13 +152,11 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
 
                 InternalConfig {
                     coretemp,
-                    high_level: c.high_level.unwrap_or(HIGH_LEVEL),
                     tick: c
                         .tick
                         .map_or(TICK_RATE, |t| Duration::from_millis(t as u64)),
                     inputs,
                     label: c.label.as_deref().unwrap_or(LABEL),
-                    high_label: c.high_label.as_deref().unwrap_or(HIGH_LABEL),
                 }
             })
             .unwrap_or_default();
@@ -172,61 +166,65 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct Temperature<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Temperature;
 
-impl<'a> Temperature<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.temperature {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Temperature {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for Temperature {
+    type Config = Config;
 
-impl<'a> Bar for Temperature<'a> {
     fn name(&self) -> &str {
         "temperature"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn levels(&self, config: Option<&Config>) -> Vec<Level> {
+        levels(config)
     }
+
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
+    }
+}
+
+/// Check the module config, as done when the module starts
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    InternalConfig::try_from(config).map(|_| ())
+}
+
+/// The default level, the temperature being high from `high_level` degrees
+fn levels(config: Option<&Config>) -> Vec<Level> {
+    let high_level = config.and_then(|c| c.high_level).unwrap_or(HIGH_LEVEL);
+    let high_label = config.and_then(|c| c.high_label.as_deref());
+    vec![Level::new(
+        high_level.into(),
+        high_label.unwrap_or(HIGH_LABEL),
+        State::High,
+    )]
 }
 
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::try_from(&main_config)?;
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
     debug!("{:#?}", config);
     let temp_dir = find_temp_dir(config.coretemp)?;
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let mut inputs = vec![];
         for i in &config.inputs {
@@ -234,19 +232,13 @@ pub fn run(
         }
         let sum: i32 = inputs.iter().sum();
         let average = ((sum as f32 / inputs.len() as f32) / 1000_f32).round() as i32;
-        let mut label = config.label;
-        if average >= config.high_level as i32 {
-            label = config.high_label;
-        }
-        tx.send(ModuleMsg(
-            key,
-            Some(format!("{average:3}°")),
-            Some(label.to_string()),
+        tx.send(Data::new(
+            Some(Value::number(average.into(), "°", 3, 0)),
+            Some(config.label),
+            State::Normal,
         ))?;
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
@@ -257,10 +249,10 @@ fn find_temp_dir(str_path: &str) -> Result<String, Error> {
     for entry in entries {
         let entry = entry?;
         let path = entry.path();
-        if path.is_dir() {
-            if let Some(p) = path.to_str() {
-                return Ok(p.to_string());
-            }
+        if path.is_dir()
+            && let Some(p) = path.to_str()
+        {
+            return Ok(p.to_string());
         }
     }
     Err(Error::new(format!(
@@ -2,15 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
 use crate::http::HTTP_CLIENT;
-use crate::module::{Bar, RunPtr};
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
+use crate::value::{Data, State, Value};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, instrument, trace, warn};
 
@@ -121,9 +119,15 @@ pub struct Config {
     text_mode: Option<bool>,
     // Update interval in seconds
     tick: Option<u32>,
-    placeholder: Option<String>,
     label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
@@ -153,13 +157,11 @@ impl<'a> Default for InternalConfig<'a> {
     }
 }
 
-impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
+impl<'a> TryFrom<Option<&'a Config>> for InternalConfig<'a> {
     type Error = Error;
 
-    fn try_from(config: &'a MainConfig) -> Result<Self, Self::Error> {
+    fn try_from(config: Option<&'a Config>) -> Result<Self, Self::Error> {
         let internal_cfg = config
-            .weather
-            .as_ref()
             .map(|c| InternalConfig {
                 location: c.location.to_owned(),
                 api_key: c.api_key.to_owned(),
@@ -177,43 +179,34 @@ impl<'a> TryFrom<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct Weather<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Weather;
 
-impl<'a> Weather<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        Weather {
-            placeholder: config
-                .weather
-                .as_ref()
-                .and_then(|c| c.placeholder.as_deref())
-                .unwrap_or(PLACEHOLDER),
-            format: config
-                .weather
-                .as_ref()
-                .and_then(|c| c.format.as_deref())
-                .unwrap_or(FORMAT),
-        }
-    }
-}
+impl Module for Weather {
+    type Config = Config;
 
-impl<'a> Bar for Weather<'a> {
     fn name(&self) -> &str {
         "weather"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn check(&self, config: Option<&Config>) -> Result<(), Error> {
+        check(config)
+    }
+
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
@@ -269,20 +262,20 @@ fn get_output(json: JsonResponse, config: &InternalConfig) -> String {
     format!("{icon} {temp}{t_symbol}")
 }
 
+/// Check the module config, as done when the module starts
+fn check(config: Option<&Config>) -> Result<(), Error> {
+    InternalConfig::try_from(config).map(|_| ())
+}
+
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::try_from(&main_config)?;
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::try_from(module_config)?;
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
     let url = build_url(&config);
     debug!("openweather URL: {}", url);
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let response = HTTP_CLIENT
             .get(&url)
@@ -297,13 +290,15 @@ pub fn run(
                 .ok()
                 .map(|json| get_output(json, &config));
             if let Some(text) = output {
-                tx.send(ModuleMsg(key, Some(text), Some(config.label.to_owned())))?;
+                tx.send(Data::new(
+                    Some(Value::Text(text)),
+                    Some(config.label),
+                    State::Normal,
+                ))?;
             }
         }
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
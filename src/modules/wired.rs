@@ -2,14 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
 use crate::netlink::{self, WiredState};
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, State};
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument, warn};
 
@@ -26,10 +24,16 @@ pub struct Config {
     tick: Option<u32>,
     interface: Option<String>,
     discrete: Option<bool>,
-    placeholder: Option<String>,
     label: Option<String>,
     disconnected_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
@@ -41,14 +45,14 @@ pub struct InternalConfig<'a> {
     disconnected_label: &'a str,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut tick = TICK_RATE;
         let mut interface = INTERFACE;
         let mut discrete = DISCRETE;
         let mut label = LABEL;
         let mut disconnected_label = DISCONNECTED_LABEL;
-        if let Some(c) = &config.wired {
+        if let Some(c) = config {
             if let Some(t) = c.tick {
                 tick = Duration::from_millis(t as u64)
             }
@@ -76,61 +80,41 @@ impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct Wired<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Wired;
 
-impl<'a> Wired<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.wired {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Wired {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for Wired {
+    type Config = Config;
 
-impl<'a> Bar for Wired<'a> {
     fn name(&self) -> &str {
         "wired"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
     let mut no_data_logged = false;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let data = netlink::wired_data(config.interface);
         if !no_data_logged && data.is_none() {
@@ -139,24 +123,22 @@ pub fn run(
         }
         match data {
             Some(WiredState::Connected) => {
-                tx.send(ModuleMsg(key, None, Some(config.label.to_string())))?;
+                tx.send(Data::new(None, Some(config.label), State::Normal))?;
             }
             _ => {
                 if config.discrete {
-                    tx.send(ModuleMsg(key, None, None))?;
+                    tx.send(Data::new(None, None, State::Disconnected))?;
                 } else {
-                    tx.send(ModuleMsg(
-                        key,
+                    tx.send(Data::new(
                         None,
-                        Some(config.disconnected_label.to_string()),
+                        Some(config.disconnected_label),
+                        State::Disconnected,
                     ))?;
                 }
             }
         }
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
@@ -2,14 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::control::Control;
 use crate::error::Error;
-use crate::module::{Bar, RunPtr};
+use crate::module::{CommonConfig, Instance, Module, ModuleSender};
 use crate::netlink::{self, WirelessState};
-use crate::{Config as MainConfig, ModuleMsg};
+use crate::value::{Data, Number, State, Value};
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
-use std::thread;
 use std::time::{Duration, Instant};
 use tracing::{debug, instrument, warn};
 
@@ -34,10 +32,16 @@ pub struct Config {
     display: Option<Display>,
     max_essid_len: Option<usize>,
     interface: Option<String>,
-    placeholder: Option<String>,
     label: Option<String>,
     disconnected_label: Option<String>,
-    format: Option<String>,
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
 }
 
 #[derive(Debug)]
@@ -50,15 +54,15 @@ pub struct InternalConfig<'a> {
     disconnected_label: &'a str,
 }
 
-impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
-    fn from(config: &'a MainConfig) -> Self {
+impl<'a> From<Option<&'a Config>> for InternalConfig<'a> {
+    fn from(config: Option<&'a Config>) -> Self {
         let mut tick = TICK_RATE;
         let mut display = DISPLAY;
         let mut max_essid_len = MAX_ESSID_LEN;
         let mut interface = INTERFACE;
         let mut label = LABEL;
         let mut disconnected_label = DISCONNECTED_LABEL;
-        if let Some(c) = &config.wireless {
+        if let Some(c) = config {
             if let Some(t) = c.tick {
                 tick = Duration::from_millis(t as u64)
             }
@@ -90,61 +94,41 @@ impl<'a> From<&'a MainConfig> for InternalConfig<'a> {
 }
 
 #[derive(Debug)]
-pub struct Wireless<'a> {
-    placeholder: &'a str,
-    format: &'a str,
-}
+pub struct Wireless;
 
-impl<'a> Wireless<'a> {
-    pub fn with_config(config: &'a MainConfig) -> Self {
-        let mut placeholder = PLACEHOLDER;
-        let mut format = FORMAT;
-        if let Some(c) = &config.wireless {
-            if let Some(p) = &c.placeholder {
-                placeholder = p
-            }
-            if let Some(v) = &c.format {
-                format = v;
-            }
-        }
-        Wireless {
-            placeholder,
-            format,
-        }
-    }
-}
+impl Module for Wireless {
+    type Config = Config;
 
-impl<'a> Bar for Wireless<'a> {
     fn name(&self) -> &str {
         "wireless"
     }
 
-    fn run_fn(&self) -> RunPtr {
-        run
+    fn format(&self) -> &str {
+        FORMAT
     }
 
     fn placeholder(&self) -> &str {
-        self.placeholder
+        PLACEHOLDER
     }
 
-    fn format(&self) -> &str {
-        self.format
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        run(control, config, tx)
     }
 }
 
 #[instrument(skip_all)]
-pub fn run(
-    running: &AtomicBool,
-    key: char,
-    main_config: MainConfig,
-    tx: Sender<ModuleMsg>,
-) -> Result<(), Error> {
-    let config = InternalConfig::from(&main_config);
+fn run(control: &Control, module_config: Option<&Config>, tx: &ModuleSender) -> Result<(), Error> {
+    let config = InternalConfig::from(module_config);
     debug!("{:#?}", config);
     let mut iteration_start: Instant;
     let mut iteration_end: Duration;
     let mut no_data_logged = false;
-    while running.load(Ordering::Relaxed) {
+    while control.is_running() {
         iteration_start = Instant::now();
         let label;
         let mut essid = "".to_owned();
@@ -154,8 +138,9 @@ pub fn run(
             warn!("no data for interface: {}", config.interface);
             no_data_logged = true;
         }
-        if let Some(state) = data {
-            if let WirelessState::Connected(data) = state {
+        if let Some(wireless_state) = data {
+            let mut state = State::Normal;
+            if let WirelessState::Connected(data) = wireless_state {
                 label = config.label;
                 if let Some(strength) = data.signal {
                     signal = Some(strength);
@@ -169,30 +154,21 @@ pub fn run(
                 }
             } else {
                 label = config.disconnected_label;
+                state = State::Disconnected;
             }
-            match config.display {
-                Display::Essid => tx.send(ModuleMsg(key, Some(essid), Some(label.to_string())))?,
-                Display::Signal => {
-                    if let Some(s) = signal {
-                        tx.send(ModuleMsg(
-                            key,
-                            Some(format!("{s:3}%")),
-                            Some(label.to_string()),
-                        ))?;
-                    } else {
-                        tx.send(ModuleMsg(
-                            key,
-                            Some("  ?%".to_string()),
-                            Some(label.to_string()),
-                        ))?;
-                    }
-                }
-            }
+            let level = signal.map(|s| Number::percent(s.into()));
+            let value = match (&config.display, &level) {
+                (Display::Essid, _) => Value::Text(essid),
+                (Display::Signal, Some(level)) => Value::Number(level.clone()),
+                (Display::Signal, None) => Value::Text("  ?%".to_string()),
+            };
+            tx.send(Data {
+                level,
+                ..Data::new(Some(value), Some(label), state)
+            })?;
         }
         iteration_end = iteration_start.elapsed();
-        if iteration_end < config.tick {
-            thread::sleep(config.tick - iteration_end);
-        }
+        control.sleep(config.tick.saturating_sub(iteration_end));
     }
     Ok(())
 }
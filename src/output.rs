@@ -0,0 +1,327 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::click::Button;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
+
+/// Markup dialect of the output, i.e. the syntax understood by the
+/// status bar reading baru
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Output {
+    /// Text only, without styling
+    #[default]
+    Plain,
+    /// https://github.com/LemonBoy/bar
+    Lemonbar,
+    /// https://codeberg.org/xmobar/xmobar
+    Xmobar,
+    /// Pango markup, e.g. waybar, polybar or dunst
+    Pango,
+    /// https://github.com/robm/dzen
+    Dzen2,
+    /// tmux `status-left`/`status-right`, e.g. through `#(baru --once)`
+    Tmux,
+    /// ANSI escape codes, for terminals
+    Ansi,
+}
+
+impl Output {
+    pub fn backend(&self) -> &'static dyn Backend {
+        match self {
+            Output::Plain => &Plain,
+            Output::Lemonbar => &Lemonbar,
+            Output::Xmobar => &Xmobar,
+            Output::Pango => &Pango,
+            Output::Dzen2 => &Dzen2,
+            Output::Tmux => &Tmux,
+            Output::Ansi => &Ansi,
+        }
+    }
+}
+
+/// Syntax of an output dialect.
+/// The module values and labels are escaped, the formats written in the
+/// config being markup of the dialect
+pub trait Backend: Debug + Sync {
+    /// Escape a text so the bar displays it verbatim
+    fn escape(&self, text: &str) -> String;
+
+    /// Wrap a text in a foreground color
+    fn color(&self, text: &str, color: &Color) -> String;
+
+    /// Wrap a text in a click area running the shell command on the given
+    /// button, `None` if the dialect has no click areas or can't hold
+    /// the command
+    fn click(&self, _text: &str, _button: Button, _command: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Color `#rrggbb` or `#rrggbbaa`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: Option<u8>,
+}
+
+impl FromStr for Color {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(format!("invalid color \"{s}\", expected #rrggbb[aa]"));
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if !matches!(hex.len(), 6 | 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Color {
+            r: byte(0)?,
+            g: byte(2)?,
+            b: byte(4)?,
+            a: if hex.len() == 8 { Some(byte(6)?) } else { None },
+        })
+    }
+}
+
+impl Color {
+    /// `#rrggbb`, without the alpha
+    fn rgb(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rgb())?;
+        if let Some(a) = self.a {
+            write!(f, "{a:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Plain;
+
+impl Backend for Plain {
+    fn escape(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    fn color(&self, text: &str, _color: &Color) -> String {
+        text.to_string()
+    }
+}
+
+#[derive(Debug)]
+struct Lemonbar;
+
+impl Backend for Lemonbar {
+    fn escape(&self, text: &str) -> String {
+        text.replace('%', "%%")
+    }
+
+    fn color(&self, text: &str, color: &Color) -> String {
+        // lemonbar expects the alpha first, #aarrggbb
+        let color = match color.a {
+            Some(a) => format!("#{:02x}{}", a, &color.rgb()[1..]),
+            None => color.rgb(),
+        };
+        format!("%{{F{color}}}{text}%{{F-}}")
+    }
+
+    fn click(&self, text: &str, button: Button, command: &str) -> Option<String> {
+        // lemonbar prints the command on its stdout, piped into a shell
+        let command = command.replace(':', "\\:");
+        Some(format!(
+            "%{{A{}:{}:}}{}%{{A}}",
+            button.code(),
+            command,
+            text
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct Xmobar;
+
+impl Backend for Xmobar {
+    fn escape(&self, text: &str) -> String {
+        match text.contains('<') {
+            true => format!("<raw={}:{}/>", text.chars().count(), text),
+            false => text.to_string(),
+        }
+    }
+
+    fn color(&self, text: &str, color: &Color) -> String {
+        format!("<fc={}>{}</fc>", color.rgb(), text)
+    }
+
+    fn click(&self, text: &str, button: Button, command: &str) -> Option<String> {
+        if command.contains('`') {
+            return None;
+        }
+        Some(format!(
+            "<action=`{}` button={}>{}</action>",
+            command,
+            button.code(),
+            text
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct Pango;
+
+impl Backend for Pango {
+    fn escape(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => output.push_str("&amp;"),
+                '<' => output.push_str("&lt;"),
+                '>' => output.push_str("&gt;"),
+                '"' => output.push_str("&quot;"),
+                '\'' => output.push_str("&apos;"),
+                c => output.push(c),
+            }
+        }
+        output
+    }
+
+    fn color(&self, text: &str, color: &Color) -> String {
+        // older pango rejects #rrggbbaa, the alpha goes into fgalpha
+        match color.a {
+            Some(a) => format!(
+                "<span foreground=\"{}\" fgalpha=\"{}%\">{}</span>",
+                color.rgb(),
+                (u32::from(a) * 100 + 127) / 255,
+                text
+            ),
+            None => format!("<span foreground=\"{}\">{}</span>", color.rgb(), text),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Dzen2;
+
+impl Backend for Dzen2 {
+    fn escape(&self, text: &str) -> String {
+        text.replace('^', "^^")
+    }
+
+    fn color(&self, text: &str, color: &Color) -> String {
+        format!("^fg({}){}^fg()", color.rgb(), text)
+    }
+
+    fn click(&self, text: &str, button: Button, command: &str) -> Option<String> {
+        // the command ends at the first closing parenthesis
+        if command.contains(')') {
+            return None;
+        }
+        Some(format!("^ca({},{}){}^ca()", button.code(), command, text))
+    }
+}
+
+#[derive(Debug)]
+struct Tmux;
+
+impl Backend for Tmux {
+    fn escape(&self, text: &str) -> String {
+        text.replace('#', "##")
+    }
+
+    fn color(&self, text: &str, color: &Color) -> String {
+        format!("#[fg={}]{}#[fg=default]", color.rgb(), text)
+    }
+}
+
+#[derive(Debug)]
+struct Ansi;
+
+impl Backend for Ansi {
+    fn escape(&self, text: &str) -> String {
+        // drop the escape codes and the other control characters
+        text.chars().filter(|c| !c.is_control()).collect()
+    }
+
+    fn color(&self, text: &str, color: &Color) -> String {
+        format!(
+            "\x1b[38;2;{};{};{}m{}\x1b[39m",
+            color.r, color.g, color.b, text
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_color() {
+        let color = Color::from_str("#FF8000").unwrap();
+        assert_eq!(color.to_string(), "#ff8000");
+        assert_eq!(Color::from_str("#ff800080").unwrap().a, Some(0x80));
+        assert!(Color::from_str("ff8000").is_err());
+        assert!(Color::from_str("#ff80").is_err());
+        assert!(Color::from_str("#gg8000").is_err());
+        assert!(Color::from_str("red").is_err());
+    }
+
+    #[test]
+    fn color_output() {
+        let color = Color::from_str("#ff800080").unwrap();
+        let color = |output: Output| output.backend().color("42%", &color);
+        assert_eq!(color(Output::Plain), "42%");
+        assert_eq!(color(Output::Lemonbar), "%{F#80ff8000}42%%{F-}");
+        assert_eq!(color(Output::Xmobar), "<fc=#ff8000>42%</fc>");
+        assert_eq!(
+            color(Output::Pango),
+            "<span foreground=\"#ff8000\" fgalpha=\"50%\">42%</span>"
+        );
+        assert_eq!(color(Output::Dzen2), "^fg(#ff8000)42%^fg()");
+        assert_eq!(color(Output::Tmux), "#[fg=#ff8000]42%#[fg=default]");
+        assert_eq!(color(Output::Ansi), "\x1b[38;2;255;128;0m42%\x1b[39m");
+        let opaque = Color::from_str("#ff8000").unwrap();
+        assert_eq!(
+            Output::Pango.backend().color("42%", &opaque),
+            "<span foreground=\"#ff8000\">42%</span>"
+        );
+    }
+
+    #[test]
+    fn escape_output() {
+        let escape = |output: Output| output.backend().escape("<100% & #1^\x1b[2J>");
+        assert_eq!(escape(Output::Plain), "<100% & #1^\x1b[2J>");
+        assert_eq!(escape(Output::Lemonbar), "<100%% & #1^\x1b[2J>");
+        assert_eq!(escape(Output::Xmobar), "<raw=16:<100% & #1^\x1b[2J>/>");
+        assert_eq!(escape(Output::Pango), "&lt;100% &amp; #1^\x1b[2J&gt;");
+        assert_eq!(escape(Output::Dzen2), "<100% & #1^^\x1b[2J>");
+        assert_eq!(escape(Output::Tmux), "<100% & ##1^\x1b[2J>");
+        assert_eq!(escape(Output::Ansi), "<100% & #1^[2J>");
+    }
+
+    #[test]
+    fn click_output() {
+        let click = |output: Output| output.backend().click("x", Button::Left, "echo a:b");
+        assert_eq!(click(Output::Plain), None);
+        assert_eq!(click(Output::Lemonbar).unwrap(), "%{A1:echo a\\:b:}x%{A}");
+        assert_eq!(
+            click(Output::Xmobar).unwrap(),
+            "<action=`echo a:b` button=1>x</action>"
+        );
+        assert_eq!(click(Output::Dzen2).unwrap(), "^ca(1,echo a:b)x^ca()");
+        let click = Output::Dzen2
+            .backend()
+            .click("x", Button::ScrollUp, "echo (a)");
+        assert_eq!(click, None);
+    }
+}
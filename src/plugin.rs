@@ -0,0 +1,380 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Modules loaded from shared libraries, see the C ABI in
+//! `lib/plugin/include/baru_plugin.h`
+
+use crate::control::Control;
+use crate::error::Error;
+use crate::module::{CommonConfig, Instance, Module, ModuleSender, Registry};
+use crate::value::{Data, Number, State, Value};
+use libloading::{Library, Symbol};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+/// Version of the plugin ABI, `BARU_PLUGIN_ABI`
+pub const ABI_VERSION: u32 = 1;
+const ENTRY_POINT: &[u8] = b"baru_plugin";
+const EXTENSION: &str = "so";
+
+/// `t_baru_host`, the functions given to a running instance
+#[repr(C)]
+struct Host {
+    ctx: *mut c_void,
+    running: extern "C" fn(*mut c_void) -> bool,
+    sleep: extern "C" fn(*mut c_void, u64),
+    send: extern "C" fn(*mut c_void, *const c_char, *const c_char, *const c_char) -> bool,
+    send_number:
+        extern "C" fn(*mut c_void, f64, *const c_char, f64, *const c_char, *const c_char) -> bool,
+    error: extern "C" fn(*mut c_void, *const c_char),
+}
+
+/// `t_baru_plugin`, exported by the plugin
+#[repr(C)]
+struct Vtable {
+    abi: u32,
+    name: *const c_char,
+    create: Option<extern "C" fn(*const c_char, *const Host) -> *mut c_void>,
+    run: Option<extern "C" fn(*mut c_void, *const Host) -> i32>,
+    destroy: Option<extern "C" fn(*mut c_void)>,
+}
+
+/// Config of a plugin instance, the options other than the common ones
+/// are given to the plugin
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    // first, to take the common options before `options`
+    #[serde(flatten)]
+    common: CommonConfig,
+    #[serde(flatten)]
+    options: Mapping,
+}
+
+impl Instance for Config {
+    fn common(&self) -> &CommonConfig {
+        &self.common
+    }
+}
+
+/// A module loaded from a shared library
+pub struct Plugin {
+    name: String,
+    vtable: *const Vtable,
+    // keeps the vtable and the plugin functions loaded
+    _library: Option<Library>,
+}
+
+// the plugin functions are required to be thread-safe by the ABI
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+/// State of a running instance, given to the host functions.
+/// The plugin may call them from its own threads while `run` is running
+struct Context<'a> {
+    control: &'a Control,
+    tx: &'a ModuleSender,
+    error: Mutex<Option<String>>,
+}
+
+impl Plugin {
+    /// Load a plugin from a shared library
+    #[instrument]
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        // SAFETY: loading a library runs its initialization code, the
+        // plugins directory is trusted like the config file
+        let library = unsafe { Library::new(path) }.map_err(|e| Error::new(e.to_string()))?;
+        let vtable = unsafe {
+            let entry: Symbol<extern "C" fn() -> *const Vtable> = library
+                .get(ENTRY_POINT)
+                .map_err(|e| Error::new(e.to_string()))?;
+            entry()
+        };
+        unsafe { Plugin::from_vtable(vtable, Some(library)) }
+    }
+
+    /// # Safety
+    /// `vtable` must be null or valid as long as `library` is loaded
+    unsafe fn from_vtable(vtable: *const Vtable, library: Option<Library>) -> Result<Self, Error> {
+        let v = unsafe { vtable.as_ref() }.ok_or("no plugin returned")?;
+        if v.abi != ABI_VERSION {
+            return Err(Error::new(format!(
+                "unsupported ABI version {}, expected {}",
+                v.abi, ABI_VERSION
+            )));
+        }
+        if v.create.is_none() || v.run.is_none() || v.destroy.is_none() {
+            return Err(Error::new("missing plugin function"));
+        }
+        let name = unsafe { string(v.name) }.ok_or("missing plugin name")?;
+        Ok(Plugin {
+            name,
+            vtable,
+            _library: library,
+        })
+    }
+}
+
+impl Module for Plugin {
+    type Config = Config;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[instrument(skip_all, fields(plugin = self.name))]
+    fn run(
+        &self,
+        control: &Control,
+        config: Option<&Config>,
+        tx: &ModuleSender,
+    ) -> Result<(), Error> {
+        // SAFETY: checked when loaded, valid while the library is loaded
+        let vtable = unsafe { &*self.vtable };
+        let (Some(create), Some(run), Some(destroy)) = (vtable.create, vtable.run, vtable.destroy)
+        else {
+            return Err(Error::new("missing plugin function"));
+        };
+        let options =
+            serde_yaml::to_string(&config.map(|c| &c.options).unwrap_or(&Mapping::new()))?;
+        let options = CString::new(options).map_err(|_| "invalid config, nul character")?;
+        let context = Context {
+            control,
+            tx,
+            error: Mutex::new(None),
+        };
+        let host = context.host();
+        let instance = create(options.as_ptr(), &host);
+        if instance.is_null() {
+            return Err(context.error("failed to create the instance"));
+        }
+        debug!("instance created");
+        let code = run(instance, &host);
+        destroy(instance);
+        match code {
+            0 => Ok(()),
+            c => Err(context.error(&format!("exited with code {c}"))),
+        }
+    }
+}
+
+impl<'a> Context<'a> {
+    fn host(&self) -> Host {
+        Host {
+            ctx: self as *const Context as *mut c_void,
+            running: host_running,
+            sleep: host_sleep,
+            send: host_send,
+            send_number: host_send_number,
+            error: host_error,
+        }
+    }
+
+    /// The error set by the plugin, or the given one
+    fn error(&self, default: &str) -> Error {
+        let message = self.error.lock().ok().and_then(|mut e| e.take());
+        Error::new(message.unwrap_or_else(|| default.to_string()))
+    }
+
+    fn send(&self, value: Option<Value>, label: *const c_char, state: *const c_char) -> bool {
+        let label = unsafe { string(label) };
+        let state = match unsafe { string(state) }.map(|s| State::from_str(&s)) {
+            Some(Ok(s)) => s,
+            Some(Err(e)) => {
+                warn!("data rejected: {}", e);
+                return false;
+            }
+            None => State::Normal,
+        };
+        self.tx
+            .send(Data::new(value, label.as_deref(), state))
+            .inspect_err(|e| warn!("failed to send data: {}", e))
+            .is_ok()
+    }
+}
+
+/// # Safety
+/// `ctx` must be the context given to the plugin
+unsafe fn context<'a>(ctx: *mut c_void) -> &'a Context<'a> {
+    unsafe { &*(ctx as *const Context) }
+}
+
+/// # Safety
+/// `s` must be null or a valid C string
+unsafe fn string(s: *const c_char) -> Option<String> {
+    match s.is_null() {
+        true => None,
+        false => Some(unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()),
+    }
+}
+
+extern "C" fn host_running(ctx: *mut c_void) -> bool {
+    unsafe { context(ctx) }.control.is_running()
+}
+
+extern "C" fn host_sleep(ctx: *mut c_void, ms: u64) {
+    unsafe { context(ctx) }
+        .control
+        .sleep(Duration::from_millis(ms));
+}
+
+extern "C" fn host_send(
+    ctx: *mut c_void,
+    value: *const c_char,
+    label: *const c_char,
+    state: *const c_char,
+) -> bool {
+    let value = unsafe { string(value) }.map(Value::Text);
+    unsafe { context(ctx) }.send(value, label, state)
+}
+
+extern "C" fn host_send_number(
+    ctx: *mut c_void,
+    value: f64,
+    unit: *const c_char,
+    max: f64,
+    label: *const c_char,
+    state: *const c_char,
+) -> bool {
+    let unit = unsafe { string(unit) }.unwrap_or_default();
+    let mut number = Number::new(value, &unit, 0, 0);
+    if max > 0.0 {
+        number = number.with_max(max);
+    }
+    unsafe { context(ctx) }.send(Some(Value::Number(number)), label, state)
+}
+
+extern "C" fn host_error(ctx: *mut c_void, message: *const c_char) {
+    let message = unsafe { string(message) };
+    if let Ok(mut error) = unsafe { context(ctx) }.error.lock() {
+        *error = message;
+    }
+}
+
+/// Load the plugins of a directory, i.e. its shared libraries, into the
+/// registry. A plugin failing to load is skipped
+#[instrument(skip(registry))]
+pub fn load_dir(dir: &Path, registry: &mut Registry) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        debug!("no plugins directory");
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == EXTENSION))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let result = Plugin::load(&path).and_then(|plugin| {
+            let name = plugin.name.clone();
+            registry.register(plugin).map(|_| name)
+        });
+        match result {
+            Ok(name) => info!("plugin \"{}\" loaded from {}", name, path.display()),
+            Err(e) => error!("failed to load plugin {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    extern "C" fn create(config: *const c_char, host: *const Host) -> *mut c_void {
+        let config = unsafe { string(config) }.unwrap();
+        if config.contains("fail") {
+            let host = unsafe { &*host };
+            (host.error)(host.ctx, c"invalid config".as_ptr());
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(CString::new(config.trim()).unwrap())) as *mut c_void
+    }
+
+    extern "C" fn run(instance: *mut c_void, host: *const Host) -> i32 {
+        let config = unsafe { &*(instance as *const CString) };
+        let host = unsafe { &*host };
+        (host.send)(
+            host.ctx,
+            config.as_ptr(),
+            c"lbl".as_ptr(),
+            c"warning".as_ptr(),
+        );
+        (host.send_number)(
+            host.ctx,
+            21.0,
+            c"%".as_ptr(),
+            42.0,
+            std::ptr::null(),
+            std::ptr::null(),
+        );
+        match (host.send)(
+            host.ctx,
+            config.as_ptr(),
+            std::ptr::null(),
+            c"nope".as_ptr(),
+        ) {
+            true => 0,
+            false => 1,
+        }
+    }
+
+    extern "C" fn destroy(instance: *mut c_void) {
+        drop(unsafe { Box::from_raw(instance as *mut CString) });
+    }
+
+    fn vtable() -> Vtable {
+        Vtable {
+            abi: ABI_VERSION,
+            name: c"test".as_ptr(),
+            create: Some(create),
+            run: Some(run),
+            destroy: Some(destroy),
+        }
+    }
+
+    fn run_plugin(config: &str) -> (Result<(), Error>, Vec<Data>) {
+        let vtable = vtable();
+        let plugin = unsafe { Plugin::from_vtable(&vtable, None) }.unwrap();
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let result = plugin.run(&Control::new(), Some(&config), &ModuleSender::new(0, tx));
+        (result, rx.try_iter().map(|m| m.1).collect())
+    }
+
+    #[test]
+    fn run_plugin_instance() {
+        let (result, data) = run_plugin("id: a\nformat: '%v'\nfoo: 1\n");
+        assert_eq!(result.unwrap_err().to_string(), "exited with code 1");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].value, Some(Value::Text("foo: 1".to_string())));
+        assert_eq!(data[0].label.as_deref(), Some("lbl"));
+        assert_eq!(data[0].state, State::Warning);
+        assert_eq!(data[1].percentage(), Some(50));
+        let (result, data) = run_plugin("foo: fail\n");
+        assert_eq!(result.unwrap_err().to_string(), "invalid config");
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn reject_plugin() {
+        let newer = Vtable {
+            abi: ABI_VERSION + 1,
+            ..vtable()
+        };
+        assert!(unsafe { Plugin::from_vtable(&newer, None) }.is_err());
+        let incomplete = Vtable {
+            run: None,
+            ..vtable()
+        };
+        assert!(unsafe { Plugin::from_vtable(&incomplete, None) }.is_err());
+    }
+}
@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::{Deserialize, Serialize};
+
+const I3BAR_VERSION: u32 = 1;
+
+/// Output protocol used to talk to the status bar
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    /// One line of text per update, built from the root `format`
+    #[default]
+    Plain,
+    /// i3bar/swaybar JSON protocol, see https://i3wm.org/docs/i3bar-protocol.html
+    I3bar,
+    /// waybar custom module JSON output, for a single module, see
+    /// https://github.com/Alexays/Waybar/wiki/Module:-Custom
+    Waybar,
+}
+
+/// i3bar protocol header, sent once at startup
+#[derive(Debug, Serialize)]
+pub struct Header {
+    version: u32,
+    click_events: bool,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            version: I3BAR_VERSION,
+            click_events: true,
+        }
+    }
+}
+
+/// i3bar protocol block, one per module
+#[derive(Debug, Serialize)]
+pub struct Block<'a> {
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<&'a str>,
+    pub full_text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    pub urgent: bool,
+    pub separator: bool,
+}
+
+/// waybar custom module output, with `return-type: json`
+#[derive(Debug, Serialize)]
+pub struct WaybarOutput<'a> {
+    pub text: &'a str,
+    pub alt: &'a str,
+    pub tooltip: String,
+    pub class: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentage: Option<u32>,
+}
+
+/// Current data of a module, `--once --json` output
+#[derive(Debug, Serialize)]
+pub struct Snapshot<'a> {
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<&'a str>,
+    pub text: &'a str,
+    pub value: Option<String>,
+    pub label: Option<&'a str>,
+    pub state: &'a str,
+    pub percentage: Option<u32>,
+}
+
+/// State of a module thread, IPC `list` output
+#[derive(Debug, Serialize)]
+pub struct ModuleStatus<'a> {
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<&'a str>,
+    pub state: &'a str,
+    pub restarts: u32,
+    pub last_error: Option<&'a str>,
+}
@@ -4,9 +4,10 @@
 
 use crate::Config;
 use crate::error::Error;
+use crate::modules::{mic, sound};
 use anyhow::Result;
 use once_cell::sync::OnceCell;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
 use std::sync::Mutex;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread::{self, JoinHandle};
@@ -16,19 +17,93 @@ use tracing::{error, info, instrument, warn};
 const PULSE_RATE: u32 = 50_000_000; // in nanosecond
 
 pub type Callback = extern "C" fn(*const CallbackContext, u32, bool);
+pub type CommandCallback = extern "C" fn(*const CallbackContext, *mut PulseCommand) -> bool;
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct PulseData(pub u32, pub bool);
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
-pub struct CallbackContext(Sender<PulseData>, Sender<PulseData>);
+pub enum Target {
+    Sink,
+    Source,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub enum CommandKind {
+    VolumeUp,
+    VolumeDown,
+    SetVolume,
+    ToggleMute,
+}
+
+/// Command executed by the PulseAudio loop on the tracked sink/source.
+/// `2`: volume step or absolute volume, in percent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct PulseCommand(pub Target, pub CommandKind, pub u32);
+
+/// 0: sink sender, 1: source sender, 2: commands receiver
+#[repr(C)]
+pub struct CallbackContext(Sender<PulseData>, Sender<PulseData>, Receiver<PulseCommand>);
 
-/// 0: sink, 1: source
-pub struct Pulse(Receiver<PulseData>, Receiver<PulseData>);
+/// 0: sink, 1: source, 2: commands sender
+pub struct Pulse(
+    Receiver<PulseData>,
+    Receiver<PulseData>,
+    Sender<PulseCommand>,
+);
 
 pub static PULSE: OnceCell<Mutex<Pulse>> = OnceCell::new();
 
+/// Options read once, when the PulseAudio thread starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    tick: u32,
+    sink_name: Option<String>,
+    source_name: Option<String>,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let tick = match config.pulse_tick {
+            Some(val) => val * 1e6 as u32,
+            None => PULSE_RATE,
+        };
+        let mut sink_name = None;
+        let mut source_name = None;
+        if let Some(c) = config.instances::<sound::Config>("sound")?.first() {
+            sink_name = c.sink_name.clone();
+        }
+        if let Some(c) = config.instances::<mic::Config>("mic")?.first() {
+            source_name = c.source_name.clone();
+        }
+        Ok(Settings {
+            tick,
+            sink_name,
+            source_name,
+        })
+    }
+}
+
+/// Check the sound and mic sections: the PulseAudio thread tracks a single
+/// sink and a single source, so each module is declared once at most.
+/// Returns the name of the faulty module along with the error
+pub fn check(config: &Config) -> Result<(), (&'static str, Error)> {
+    for (name, device) in [("sound", "sink"), ("mic", "source")] {
+        if let Some(serde_yaml::Value::Sequence(instances)) = config.section(name)
+            && instances.len() > 1
+        {
+            let message =
+                format!("{name}: a single instance is supported, PulseAudio tracks one {device}");
+            return Err((name, Error::new(message)));
+        }
+    }
+    Ok(())
+}
+
 #[instrument(skip_all)]
 pub fn init(config: &Config) -> Result<JoinHandle<Result<(), Error>>> {
     let (pulse, handle) = Pulse::new(config).inspect_err(|e| {
@@ -50,21 +125,15 @@ impl Pulse {
     pub fn new(config: &Config) -> Result<(Self, JoinHandle<Result<(), Error>>), Error> {
         let (sink_tx, sink_rx) = mpsc::channel();
         let (source_tx, source_rx) = mpsc::channel();
-        let tick = match config.pulse_tick {
-            Some(val) => val * 1e6 as u32,
-            None => PULSE_RATE,
-        };
-        let mut sink_name = None;
-        let mut source_name = None;
-        if let Some(c) = &config.sound {
-            sink_name = c.sink_name.clone();
-        }
-        if let Some(c) = &config.mic {
-            source_name = c.source_name.clone();
-        }
+        let (command_tx, command_rx) = mpsc::channel();
+        let Settings {
+            tick,
+            sink_name,
+            source_name,
+        } = Settings::new(config)?;
         let builder = thread::Builder::new().name("pulse".into());
         let handle = builder.spawn(move || -> Result<(), Error> {
-            let cb_context = CallbackContext(sink_tx, source_tx);
+            let cb_context = CallbackContext(sink_tx, source_tx, command_rx);
             pulse_run(
                 tick,
                 sink_name,
@@ -72,11 +141,12 @@ impl Pulse {
                 &cb_context,
                 sink_cb,
                 source_cb,
+                command_cb,
             );
             info!("pulse module stopped");
             Ok(())
         })?;
-        Ok((Pulse(sink_rx, source_rx), handle))
+        Ok((Pulse(sink_rx, source_rx, command_tx), handle))
     }
 
     pub fn sink_data(&self) -> Option<PulseData> {
@@ -86,6 +156,25 @@ impl Pulse {
     pub fn source_data(&self) -> Option<PulseData> {
         self.1.try_iter().last()
     }
+
+    pub fn send(&self, command: PulseCommand) -> Result<(), Error> {
+        self.2.send(command)?;
+        Ok(())
+    }
+}
+
+/// Send a command to the PulseAudio thread, it is executed on its next tick
+#[instrument]
+pub fn send_command(command: PulseCommand) -> Result<(), Error> {
+    PULSE
+        .get()
+        .ok_or("pulse module not initialized")?
+        .lock()
+        .map_err(|e| {
+            error!("failed to lock pulse module: {}", e);
+            Error::new("failed to lock pulse module")
+        })?
+        .send(command)
 }
 
 extern "C" fn sink_cb(context: *const CallbackContext, volume: u32, mute: bool) {
@@ -106,6 +195,18 @@ extern "C" fn source_cb(context: *const CallbackContext, volume: u32, mute: bool
     }
 }
 
+extern "C" fn command_cb(context: *const CallbackContext, command: *mut PulseCommand) -> bool {
+    unsafe {
+        match (*context).2.try_recv() {
+            Ok(cmd) => {
+                *command = cmd;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
 #[link(name = "audio", kind = "static")]
 unsafe extern "C" {
     fn run(
@@ -113,9 +214,10 @@ unsafe extern "C" {
         tick: u32,
         sink_name: *const c_char,
         source_name: *const c_char,
-        cb_context: *const CallbackContext,
+        cb_context: *const c_void,
         sink_cb: Callback,
         source_cb: Callback,
+        command_cb: CommandCallback,
     );
 }
 
@@ -126,6 +228,7 @@ pub fn pulse_run(
     callback_context: &CallbackContext,
     sink_cb: Callback,
     source_cb: Callback,
+    command_cb: CommandCallback,
 ) {
     let context_ptr: *const CallbackContext = callback_context;
     let mut ptr_sink = ptr::null();
@@ -147,9 +250,29 @@ pub fn pulse_run(
             tick,
             ptr_sink,
             ptr_source,
-            context_ptr,
+            context_ptr.cast(),
             sink_cb,
             source_cb,
+            command_cb,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_instances() {
+        let config = |yaml| serde_yaml::from_str::<Config>(yaml).unwrap();
+        assert!(check(&config("format: '%s'\nsound:\n  sink_name: a\n")).is_ok());
+        assert!(check(&config("format: '%s'\nsound:\n  - id: a\n")).is_ok());
+        let (name, error) =
+            check(&config("format: '%i'\nmic:\n  - id: a\n  - id: b\n")).unwrap_err();
+        assert_eq!(name, "mic");
+        assert_eq!(
+            error.to_string(),
+            "mic: a single instance is supported, PulseAudio tracks one source"
         );
     }
 }
@@ -2,27 +2,46 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::RUN;
+use crate::{RELOAD, RUN, WAKE};
 
 use anyhow::Result;
-use signal_hook::consts::{SIGINT, SIGQUIT, SIGTERM};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
 use signal_hook::iterator::Signals;
 use std::process::exit;
 use std::sync::atomic::Ordering;
 use std::thread;
 use std::time::Duration;
-use tracing::info;
+use tracing::{debug, info};
 
-const SIGNALS: [i32; 3] = [SIGINT, SIGTERM, SIGQUIT];
+const SIGNALS: [i32; 4] = [SIGINT, SIGTERM, SIGQUIT, SIGHUP];
 const EXIT_TIMEOUT: Duration = Duration::from_millis(500);
 
+/// Highest `n` of the real-time signals `SIGRTMIN+n`
+pub fn max_rt_signal() -> u32 {
+    (libc::SIGRTMAX() - libc::SIGRTMIN()) as u32
+}
+
 pub fn catch_signals() -> Result<()> {
-    let mut signals = Signals::new(SIGNALS)?;
+    // every real-time signal is caught, their default action
+    // would terminate baru
+    let rt_signals = libc::SIGRTMIN()..=libc::SIGRTMAX();
+    let mut signals = Signals::new(SIGNALS.into_iter().chain(rt_signals.clone()))?;
     let builder = thread::Builder::new().name("signal_handler".into());
 
     builder.spawn(move || {
-        if let Some(sig) = signals.forever().next() {
+        for sig in signals.forever() {
             match sig {
+                SIGHUP => {
+                    info!("received {sig}:SIGHUP, reloading config");
+                    RELOAD.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                s if rt_signals.contains(&s) => {
+                    let n = s - libc::SIGRTMIN();
+                    debug!("received {sig}:SIGRTMIN+{n}");
+                    WAKE.fetch_or(1 << n, Ordering::Relaxed);
+                    continue;
+                }
                 SIGINT => info!("received {sig}:SIGINT"),
                 SIGTERM => info!("received {sig}:SIGTERM"),
                 SIGQUIT => info!("received {sig}:SIGQUIT"),
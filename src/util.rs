@@ -3,11 +3,18 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use anyhow::{Context, Result};
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 use std::{fs, path::PathBuf};
-use tracing::{debug, error, instrument};
+use tracing::{debug, error, instrument, warn};
 
 use crate::error::Error;
 
+const POLL_RATE: Duration = Duration::from_millis(10);
+
 /// Check if a directory exists, if not create it including all
 /// parent components
 #[instrument]
@@ -36,3 +43,78 @@ pub fn read_and_parse(file: &str) -> Result<i32, Error> {
         .inspect_err(|e| error!("failed to parse the file `{}`: {}", file, e))?;
     Ok(data)
 }
+
+/// Build a shell command running in its own process group, so that the
+/// command and its children can be killed at once with [`kill_group`].
+/// The command is terminated if the thread spawning it exits.
+pub fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0);
+    // prctl is async-signal-safe, it can run between fork and exec
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    cmd
+}
+
+/// Send a signal to the process group of a command built by [`shell_command`]
+pub fn kill_group(child: &Child, signal: i32) {
+    // the process group id is the pid of the shell
+    unsafe { libc::kill(-(child.id() as i32), signal) };
+}
+
+/// Run the command in a shell and get its exit status and output.
+/// The command and its children are killed on timeout or once `stop`
+/// returns true, `None` is then returned. The children left once the shell
+/// has exited are killed too, the output ends with the shell
+pub fn run_command(
+    command: &str,
+    timeout: Duration,
+    stop: impl Fn() -> bool,
+) -> Result<Option<(ExitStatus, String)>, Error> {
+    let mut child = shell_command(command).stdout(Stdio::piped()).spawn()?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or("failed to get the command output")?;
+    let reader = thread::Builder::new()
+        .name("command_output".into())
+        .spawn(move || {
+            let mut output = vec![];
+            stdout
+                .read_to_end(&mut output)
+                .map(|_| String::from_utf8_lossy(&output).into_owned())
+        })?;
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if stop() {
+            break None;
+        }
+        if Instant::now() >= deadline {
+            warn!("command `{}` timed out after {:?}", command, timeout);
+            break None;
+        }
+        thread::sleep(POLL_RATE);
+    };
+    // the background children may keep the output open
+    kill_group(&child, libc::SIGKILL);
+    if status.is_none() {
+        child.wait()?;
+    }
+    let output = reader
+        .join()
+        .map_err(|_| Error::new("command output reader panicked"))??;
+    Ok(status.map(|s| (s, output)))
+}
@@ -0,0 +1,387 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Data sent by a module at each update
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Data {
+    /// Displayed value, `%v`
+    pub value: Option<Value>,
+    /// Displayed label, `%l`
+    pub label: Option<String>,
+    pub state: State,
+    /// Measured level when the displayed value is not a single number,
+    /// e.g. the signal strength of a wireless module displaying its essid
+    pub level: Option<Number>,
+}
+
+/// State of a module value, based on the module thresholds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    #[default]
+    Normal,
+    High,
+    Low,
+    Charging,
+    Full,
+    /// Unknown status, e.g. a battery neither charging nor discharging
+    Unknown,
+    Muted,
+    Disconnected,
+    Warning,
+    Critical,
+}
+
+/// Value sent by a module, formatted by the core using the
+/// specifier of the `%v` markup, e.g. `%v{>4}` or `%v{.1}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(Number),
+    /// Two numbers displayed as `a/b`, e.g. used/total memory
+    Fraction(Number, Number),
+}
+
+/// Number and its unit, with its optional maximum
+/// and the module default specifier
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number {
+    pub value: f64,
+    pub unit: String,
+    pub max: Option<f64>,
+    spec: Spec,
+}
+
+/// Format specifier, `[[fill]align][width][.precision]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    width: Option<usize>,
+    precision: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        }
+    }
+}
+
+impl Number {
+    /// `width` and `precision` are the default ones of the module,
+    /// overridden by the specifier of the markup
+    pub fn new(value: f64, unit: &str, width: usize, precision: usize) -> Self {
+        Number {
+            value,
+            unit: unit.to_string(),
+            max: None,
+            spec: Spec {
+                width: Some(width),
+                precision: Some(precision),
+                ..Spec::default()
+            },
+        }
+    }
+
+    /// A percentage, e.g. a battery level or a volume
+    pub fn percent(value: f64) -> Self {
+        Number::new(value, "%", 3, 0).with_max(100.0)
+    }
+
+    pub fn with_max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// The number relative to its maximum, in percent
+    pub fn percentage(&self) -> Option<u32> {
+        self.max
+            .filter(|max| *max > 0.0)
+            .map(|max| (self.value * 100.0 / max).round().max(0.0) as u32)
+    }
+
+    fn format(&self, spec: &Spec) -> String {
+        let spec = spec.or(&self.spec);
+        let number = format!("{:.*}", spec.precision.unwrap_or(0), self.value);
+        let mut output = spec.pad(&number, Align::Right);
+        output.push_str(&self.unit);
+        output
+    }
+}
+
+impl Data {
+    pub fn new(value: Option<Value>, label: Option<&str>, state: State) -> Self {
+        Data {
+            value,
+            label: label.map(|l| l.to_string()),
+            state,
+            level: None,
+        }
+    }
+
+    /// The measured level of the module, i.e. its `level` or
+    /// its value if it is a number
+    pub fn level(&self) -> Option<&Number> {
+        match (&self.level, &self.value) {
+            (Some(level), _) => Some(level),
+            (None, Some(Value::Number(number))) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn percentage(&self) -> Option<u32> {
+        self.level().and_then(|l| l.percentage())
+    }
+}
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Normal => "normal",
+            State::High => "high",
+            State::Low => "low",
+            State::Charging => "charging",
+            State::Full => "full",
+            State::Unknown => "unknown",
+            State::Muted => "muted",
+            State::Disconnected => "disconnected",
+            State::Warning => "warning",
+            State::Critical => "critical",
+        }
+    }
+}
+
+impl FromStr for State {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            State::Normal,
+            State::High,
+            State::Low,
+            State::Charging,
+            State::Full,
+            State::Unknown,
+            State::Muted,
+            State::Disconnected,
+            State::Warning,
+            State::Critical,
+        ]
+        .into_iter()
+        .find(|state| state.as_str() == s)
+        .ok_or_else(|| Error::new(format!("unknown state \"{s}\"")))
+    }
+}
+
+impl Value {
+    pub fn number(value: f64, unit: &str, width: usize, precision: usize) -> Self {
+        Value::Number(Number::new(value, unit, width, precision))
+    }
+
+    pub fn percent(value: f64) -> Self {
+        Value::Number(Number::percent(value))
+    }
+
+    pub fn format(&self, spec: &Spec) -> String {
+        match self {
+            Value::Text(text) => {
+                let text = match spec.precision {
+                    Some(p) => text.chars().take(p).collect(),
+                    None => text.to_string(),
+                };
+                spec.pad(&text, Align::Left)
+            }
+            Value::Number(number) => number.format(spec),
+            Value::Fraction(a, b) => format!("{}/{}", a.format(spec), b.format(spec)),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(&Spec::default()))
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl Spec {
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let invalid = || Error::new(format!("invalid format specifier \"{spec}\""));
+        let mut result = Spec::default();
+        let mut chars = spec.chars().peekable();
+        let mut rest = spec.chars();
+        match (rest.next(), rest.next().and_then(Align::from_char)) {
+            (Some(fill), Some(align)) => {
+                result.fill = Some(fill);
+                result.align = Some(align);
+                chars.nth(1);
+            }
+            (Some(c), _) if Align::from_char(c).is_some() => {
+                result.align = Align::from_char(c);
+                chars.next();
+            }
+            _ => {}
+        }
+        let mut width = String::new();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            width.push(c);
+        }
+        if !width.is_empty() {
+            result.width = Some(width.parse().map_err(|_| invalid())?);
+        }
+        if chars.next_if_eq(&'.').is_some() {
+            let precision: String = chars.by_ref().collect();
+            result.precision = Some(precision.parse().map_err(|_| invalid())?);
+        }
+        if chars.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(result)
+    }
+
+    /// Merge with a default specifier, the fields of `self` take precedence
+    fn or(&self, default: &Spec) -> Spec {
+        Spec {
+            fill: self.fill.or(default.fill),
+            align: self.align.or(default.align),
+            width: self.width.or(default.width),
+            precision: self.precision.or(default.precision),
+        }
+    }
+
+    fn pad(&self, s: &str, default_align: Align) -> String {
+        let len = s.chars().count();
+        let Some(width) = self.width.filter(|w| *w > len) else {
+            return s.to_string();
+        };
+        let fill = self.fill.unwrap_or(' ');
+        let (left, right) = match self.align.unwrap_or(default_align) {
+            Align::Left => (0, width - len),
+            Align::Center => ((width - len) / 2, width - len - (width - len) / 2),
+            Align::Right => (width - len, 0),
+        };
+        let mut output = String::with_capacity(width);
+        output.extend(std::iter::repeat_n(fill, left));
+        output.push_str(s);
+        output.extend(std::iter::repeat_n(fill, right));
+        output
+    }
+}
+
+/// Replace the `%v` markups of a module format by the given value,
+/// each one with its optional specifier, e.g. `%v{>4}`.
+/// The formatted value is escaped for the output dialect
+pub fn replace_value(
+    format: &str,
+    value: Option<&Value>,
+    escape: impl Fn(&str) -> String,
+) -> Result<String, Error> {
+    let mut output = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(i) = rest.find("%v") {
+        output.push_str(&rest[..i]);
+        rest = &rest[i + 2..];
+        let mut spec = Spec::default();
+        if let Some(s) = rest.strip_prefix('{') {
+            let end = s
+                .find('}')
+                .ok_or_else(|| Error::new(format!("unterminated specifier in \"{format}\"")))?;
+            spec = Spec::parse(&s[..end])?;
+            rest = &s[end + 1..];
+        }
+        if let Some(v) = value {
+            output.push_str(&escape(&v.format(&spec)));
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spec() {
+        let spec = Spec::parse("*^6.2").unwrap();
+        assert_eq!(spec.fill, Some('*'));
+        assert_eq!(spec.align, Some(Align::Center));
+        assert_eq!(spec.width, Some(6));
+        assert_eq!(spec.precision, Some(2));
+        assert_eq!(Spec::parse(">4").unwrap().width, Some(4));
+        assert_eq!(Spec::parse(".1").unwrap().precision, Some(1));
+        assert_eq!(Spec::parse("").unwrap(), Spec::default());
+    }
+
+    #[test]
+    fn parse_invalid_spec() {
+        assert!(Spec::parse("4x").is_err());
+        assert!(Spec::parse(".").is_err());
+        assert!(Spec::parse(">>>").is_err());
+    }
+
+    #[test]
+    fn replace_number() {
+        let value = Value::number(42.0, "%", 3, 0);
+        let replace = |f| replace_value(f, Some(&value), str::to_string).unwrap();
+        assert_eq!(replace("%v"), " 42%");
+        assert_eq!(replace("%v{}"), " 42%");
+        assert_eq!(replace("%v{<4}|"), "42  %|");
+        assert_eq!(replace("%v{0>5.1}"), "042.0%");
+        assert_eq!(replace("%v{1}"), "42%");
+        assert!(replace_value("%v{", Some(&value), str::to_string).is_err());
+    }
+
+    #[test]
+    fn data_percentage() {
+        let data = Data::new(Some(Value::percent(42.0)), None, State::Normal);
+        assert_eq!(data.percentage(), Some(42));
+        let data = Data {
+            level: Some(Number::new(1.5, "GHz", 3, 1).with_max(3.0)),
+            ..Data::new(Some(Value::Text("x".to_string())), None, State::High)
+        };
+        assert_eq!(data.percentage(), Some(50));
+        let data = Data::new(Some(Value::number(60.0, "°", 3, 0)), None, State::Normal);
+        assert_eq!(data.percentage(), None);
+    }
+
+    #[test]
+    fn replace_text() {
+        let value = Value::Text("wlan".to_string());
+        let replace = |f| replace_value(f, Some(&value), str::to_string).unwrap();
+        assert_eq!(replace("[%v{6}]"), "[wlan  ]");
+        assert_eq!(replace("[%v{>6}]"), "[  wlan]");
+        assert_eq!(replace("[%v{.2}]"), "[wl]");
+        assert_eq!(replace_value("[%v]", None, str::to_string).unwrap(), "[]");
+        let escape = |s: &str| s.replace('%', "%%");
+        assert_eq!(
+            replace_value("%v{>4}%", Some(&value), escape).unwrap(),
+            "wlan%"
+        );
+        let value = Value::percent(42.0);
+        assert_eq!(replace_value("%v", Some(&value), escape).unwrap(), " 42%%");
+    }
+}